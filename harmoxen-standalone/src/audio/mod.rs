@@ -7,7 +7,12 @@ use std::thread;
 
 use harmoxen::backend::Event;
 // use crate::util::*;
-use harmoxen::data::{icp, sheet::*, Range};
+use harmoxen::data::{
+	icp,
+	sheet::*,
+	track::{self, Target},
+	Playback, Range, TempoMap, Track,
+};
 
 pub struct AudioBackend {
 	to_backend: Sender<Event>,
@@ -80,10 +85,12 @@ where
 					}
 					let data_len = data.len() / nb_channels as usize;
 					let mut i = 0;
+					let mut until_update = 0;
 					for frame in data.chunks_mut(nb_channels) {
-						if i % 256 == 0 {
-							engine.update((data_len - i).min(256));
+						if until_update == 0 {
+							until_update = engine.update((data_len - i).min(256));
 						}
+						until_update -= 1;
 						i += 1;
 						let value = cpal::Sample::from::<f32>(&(engine.next_sample() as f32));
 						for sample in frame.iter_mut() {
@@ -102,20 +109,28 @@ use synth::Synth;
 
 struct Engine {
 	sheet: Sheet,
+	tracks: Vec<bool>,
+	preview: bool,
 	cursor: f32,
 	active: bool,
 	synth: Synth,
-	tempo: f32,
+	tempo: TempoMap,
+	playback: Playback,
+	jump: Option<Option<f32>>, // where to continue after reaching the end of the loop, None if stopping
 }
 
 impl Engine {
 	pub fn new(period: f32) -> Engine {
 		Engine {
 			sheet: Sheet::default(),
+			tracks: vec![],
+			preview: true,
 			cursor: 0.0,
 			active: false,
 			synth: Synth::new(period),
-			tempo: 140.0,
+			tempo: TempoMap::default(),
+			playback: Playback::default(),
+			jump: None,
 		}
 	}
 
@@ -124,9 +139,14 @@ impl Engine {
 			Event::SetTempo(tempo) => {
 				self.tempo = tempo;
 			}
-			Event::PlayStart(sheet, cursor) => {
+			Event::PlaybackChanged(playback) => {
+				self.playback = playback;
+			}
+			Event::PlayStart(sheet, tracks, cursor) => {
 				self.cursor = cursor;
-				self.synth.process_events(&sheet.get_events_at_time(cursor));
+				self.jump = None;
+				self.tracks = routed(&tracks);
+				self.synth.process_events(&sheet.get_events_at_time(cursor, &self.tracks));
 				self.sheet = sheet;
 				self.active = true;
 			}
@@ -137,29 +157,66 @@ impl Engine {
 			Event::SheetChanged(sheet) => {
 				self.sheet = sheet;
 			}
+			Event::TracksChanged(tracks, current) => {
+				self.tracks = routed(&tracks);
+				self.preview = tracks.get(current).map_or(true, |track| track.target == Target::Audio);
+			}
 			Event::ICP(icp) => {
-				self.synth.process_events(&[icp]);
+				if self.preview || !matches!(icp, icp::Event::NotePlay(_)) {
+					self.synth.process_events(&[icp]);
+				}
 			}
 			_ => {}
 		}
 	}
 
-	pub fn update(&mut self, samples: usize) {
-		if self.active {
-			let length = samples as f32 * self.synth.period * (self.tempo / 60.0);
-			let range = Range(self.cursor, self.cursor + length);
-			self.cursor += length;
-			let mut events = self.sheet.get_events(range);
-			let wrap_size = self.sheet.get_size().ceil();
-			if self.cursor > wrap_size {
-				self.cursor %= wrap_size;
-				events.extend(self.sheet.get_events(Range(0.0, self.cursor)));
+	// plays up to the given number of samples, stopping early at the end of the loop
+	// returns the number of samples until the next update
+	pub fn update(&mut self, samples: usize) -> usize {
+		if !self.active {
+			return samples;
+		}
+		let mut events = vec![];
+		if let Some(jump) = self.jump.take() {
+			// the previous samples ended exactly at the end of the loop
+			events.push(icp::Event::NoteStopAll);
+			match jump {
+				Some(start) => {
+					self.cursor = start;
+					events.extend(self.sheet.get_events_at_time(start, &self.tracks));
+				}
+				None => {
+					self.active = false;
+					self.synth.process_events(&events);
+					return samples;
+				}
 			}
-			self.synth.process_events(&events);
 		}
+		let (boundary, jump) = self.playback.boundary(self.cursor, &self.sheet);
+		let until_boundary = (self.tempo.duration(self.cursor, boundary) / self.synth.period)
+			.ceil()
+			.max(1.0) as usize;
+		let samples = if until_boundary > samples {
+			let end = self.tempo.advance(self.cursor, samples as f32 * self.synth.period);
+			events.extend(self.sheet.get_events(Range(self.cursor, end), &self.tracks));
+			self.cursor = end;
+			samples
+		} else {
+			events.extend(self.sheet.get_events(Range(self.cursor, boundary), &self.tracks));
+			self.cursor = boundary;
+			self.jump = Some(jump);
+			until_boundary
+		};
+		events.extend(self.sheet.get_bend_events(self.cursor, &self.tracks));
+		self.synth.process_events(&events);
+		samples
 	}
 
 	pub fn next_sample(&mut self) -> f32 {
 		self.synth.next_sample()
 	}
 }
+
+fn routed(tracks: &[Track]) -> Vec<bool> {
+	track::routed(tracks, |target| target == Target::Audio)
+}
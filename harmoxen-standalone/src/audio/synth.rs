@@ -31,7 +31,7 @@ impl Voice {
 	pub fn next(&mut self, delta: f32) -> f32 {
 		let mut out = 0.0;
 		out += self.osc.next(delta * self.note.freq);
-		out *= self.adsr.sample();
+		out *= self.adsr.sample() * self.note.velocity;
 		self.adsr.advance(delta);
 		out
 	}
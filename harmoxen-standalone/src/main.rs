@@ -3,26 +3,38 @@ use std::sync::mpsc::*;
 mod audio;
 mod midi;
 
-use harmoxen::{Backend, Event};
+use harmoxen::{Backend, BackendEvent, Event};
 
 fn main() {
 	let (to_server, from_frontend) = channel::<Event>();
 
 	std::thread::spawn(move || {
-		let mut backend: Box<dyn Backend> = Box::new(audio::AudioBackend::new());
+		// tracks targeting audio are always played, and tracks targeting MIDI are played when a port is selected
+		let mut audio_backend: Box<dyn Backend> = Box::new(audio::AudioBackend::new());
+		let mut midi_backend: Option<Box<dyn Backend>> = None;
 
 		while let Ok(event) = from_frontend.recv() {
 			match event {
 				Event::ChangeBackend(harmoxen::BackendId::Audio) => {
-					backend = Box::new(audio::AudioBackend::new());
+					if let Some(mut backend) = midi_backend.take() {
+						backend.send(BackendEvent::Shutdown);
+					}
 				}
 				Event::ChangeBackend(harmoxen::BackendId::Midi(port)) => {
+					if let Some(mut backend) = midi_backend.take() {
+						backend.send(BackendEvent::Shutdown);
+					}
 					let output = midir::MidiOutput::new("harmoxen MIDI output").unwrap();
 					let port = output.ports().drain(..).skip(port).next().unwrap();
 					print!("{:?}", output.port_name(&port));
-					backend = Box::new(midi::MidiBackend::new(port));
+					midi_backend = Some(Box::new(midi::MidiBackend::new(port)));
+				}
+				Event::ToBackend(evt) => {
+					if let Some(backend) = &mut midi_backend {
+						backend.send(evt.clone());
+					}
+					audio_backend.send(evt);
 				}
-				Event::ToBackend(evt) => backend.send(evt),
 			}
 		}
 	});
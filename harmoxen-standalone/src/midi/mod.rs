@@ -1,6 +1,11 @@
 use harmoxen::{
 	backend::Event,
-	data::{icp, sheet::*, Range},
+	data::{
+		icp,
+		sheet::*,
+		track::{self, ChannelGroup, Target},
+		Playback, Range, TempoMap, Track,
+	},
 };
 use midir::{MidiOutput, MidiOutputConnection, MidiOutputPort};
 use std::error::Error;
@@ -51,25 +56,40 @@ pub fn run(receiver: Receiver<Event>, port: MidiOutputPort) -> Result<(), Box<dy
 					Event::SetTempo(t) => {
 						engine.tempo = t;
 					}
-					Event::PlayStart(sheet, start) => {
+					Event::PlaybackChanged(playback) => {
+						engine.playback = playback;
+					}
+					Event::PlayStart(sheet, tracks, start) => {
 						engine.cursor = start;
 						engine.setup_mpe().unwrap();
-						for event in sheet.get_events_at_time(start) {
-							engine.process_icp(event);
+						engine.set_tracks(&tracks);
+						for (group, tracks) in engine.routes.clone() {
+							for event in sheet.get_events_at_time(start, &tracks) {
+								engine.process_icp(event, group);
+							}
 						}
 						engine.sheet = sheet;
 						engine.active = true;
 					}
+					Event::TracksChanged(tracks, current) => {
+						engine.set_tracks(&tracks);
+						engine.preview = match tracks.get(current).map(|track| track.target) {
+							Some(Target::Midi(group)) => Some(group),
+							Some(Target::Audio) => None,
+							None => Some(ChannelGroup::ALL),
+						};
+					}
 					Event::PlayStop => {
-						engine.process_icp(icp::Event::NoteStopAll);
+						engine.process_icp(icp::Event::NoteStopAll, ChannelGroup::ALL);
 						engine.active = false;
 					}
 					Event::SheetChanged(sheet) => {
 						engine.sheet = sheet;
 					}
-					Event::ICP(event) => {
-						engine.process_icp(event);
-					}
+					Event::ICP(event) => match (event, engine.preview) {
+						(icp::Event::NotePlay(_), None) => {}
+						(event, group) => engine.process_icp(event, group.unwrap_or(ChannelGroup::ALL)),
+					},
 					Event::Shutdown => {
 						running = false;
 					}
@@ -92,16 +112,26 @@ const PITCH_BEND_CENTS: u8 = ((PITCH_BEND_RANGE % 1.0) * 100.0) as u8;
 
 #[derive(Clone, Copy, Default)]
 struct Channel {
-	current: Option<icp::NoteId>,
+	current: Option<icp::Note>,
 	note_number: u8,
 }
 
+impl Channel {
+	fn is_playing(&self, id: icp::NoteId) -> bool {
+		self.current.map(|note| note.id) == Some(id)
+	}
+}
+
 struct Engine {
 	conn: MidiOutputConnection,
-	tempo: f32,
+	tempo: TempoMap,
+	playback: Playback,
 	active: bool,
 	cursor: f32,
 	sheet: Sheet,
+	// which tracks to play on each group of channels
+	routes: Vec<(ChannelGroup, Vec<bool>)>,
+	preview: Option<ChannelGroup>,
 	channels: Vec<Channel>,
 }
 
@@ -112,10 +142,13 @@ impl Engine {
 
 		Ok(Engine {
 			conn,
-			tempo: 140.0,
+			tempo: TempoMap::default(),
+			playback: Playback::default(),
 			active: false,
 			cursor: 0.0,
 			sheet: Sheet::default(),
+			routes: vec![],
+			preview: Some(ChannelGroup::ALL),
 			channels: vec![Channel::default(); 15],
 		})
 	}
@@ -146,31 +179,74 @@ impl Engine {
 		Ok(())
 	}
 
+	fn set_tracks(&mut self, tracks: &[Track]) {
+		let mut routes: Vec<(ChannelGroup, Vec<bool>)> = vec![];
+		for track in tracks {
+			if let Target::Midi(group) = track.target {
+				if routes.iter().all(|&(g, _)| g != group) {
+					routes.push((group, track::routed(tracks, |target| target == Target::Midi(group))));
+				}
+			}
+		}
+		self.routes = routes;
+	}
+
 	pub fn update(&mut self, dt: f32) {
-		let length = dt * (self.tempo / 60.0);
-		let range = Range(self.cursor, self.cursor + length);
-		self.cursor += length;
-		let mut events = self.sheet.get_events(range);
-		if self.cursor > self.sheet.get_size() {
-			self.cursor %= self.sheet.get_size();
-			events.extend(self.sheet.get_events(Range(0.0, self.cursor)));
+		let mut remaining = dt;
+		while self.active && remaining > 0.0 {
+			let (boundary, jump) = self.playback.boundary(self.cursor, &self.sheet);
+			let until_boundary = self.tempo.duration(self.cursor, boundary).max(0.0);
+			let reached = remaining >= until_boundary;
+			let end = if reached {
+				boundary.max(self.cursor)
+			} else {
+				self.tempo.advance(self.cursor, remaining)
+			};
+			self.play(Range(self.cursor, end));
+			self.cursor = end;
+			remaining -= until_boundary.min(remaining);
+			if reached {
+				self.process_icp(icp::Event::NoteStopAll, ChannelGroup::ALL);
+				match jump {
+					Some(start) => {
+						self.cursor = start;
+						for (group, tracks) in self.routes.clone() {
+							for event in self.sheet.get_events_at_time(start, &tracks) {
+								self.process_icp(event, group);
+							}
+						}
+					}
+					None => self.active = false,
+				}
+			}
+		}
+	}
+
+	fn play(&mut self, range: Range) {
+		for (group, tracks) in self.routes.clone() {
+			let mut events = self.sheet.get_events(range, &tracks);
+			events.extend(self.sheet.get_bend_events(range.1, &tracks));
+			for event in events {
+				self.process_icp(event, group);
+			}
 		}
-		for event in events {
-			self.process_icp(event);
+		// stop the notes of tracks that were routed elsewhere while playing
+		for event in self.sheet.get_events(range, &[]) {
+			self.process_icp(event, ChannelGroup::ALL);
 		}
 	}
 
-	fn process_icp(&mut self, event: icp::Event) {
+	fn process_icp(&mut self, event: icp::Event, group: ChannelGroup) {
 		match event {
 			icp::Event::NotePlay(note) => {
-				let free = self.channels.iter().position(|x| x.current == None);
+				let free = (0..self.channels.len()).find(|&ch| group.contains(ch) && self.channels[ch].current.is_none());
 				if let Some(ch) = free {
 					self.note_on(ch, note).unwrap();
 				}
 			}
 			icp::Event::NoteStop(id) => {
 				for ch in 0..self.channels.len() {
-					if self.channels[ch].current == Some(id) {
+					if self.channels[ch].is_playing(id) {
 						self.note_off(ch).unwrap();
 					}
 				}
@@ -183,13 +259,13 @@ impl Engine {
 			icp::Event::NoteChangeFreq(id, freq) => {
 				for ch in 0..self.channels.len() {
 					let channel = self.channels[ch];
-					if channel.current == Some(id) {
+					if let Some(note) = channel.current.filter(|note| note.id == id) {
 						let pitch_bend = ((freq / 440.0).log2() * 12.0 + 69.0) - channel.note_number as f32;
 						if pitch_bend.abs() < PITCH_BEND_RANGE {
 							self.conn.send(&pitch_bend_msg(ch, pitch_bend)).unwrap();
 						} else {
 							self.note_off(ch).unwrap();
-							self.note_on(ch, icp::Note { id, freq }).unwrap();
+							self.note_on(ch, icp::Note { freq, ..note }).unwrap();
 						}
 					}
 				}
@@ -200,10 +276,13 @@ impl Engine {
 	fn note_on(&mut self, ch: usize, note: icp::Note) -> Result<(), Box<dyn Error>> {
 		let note_number = ((note.freq / 440.0).log2() * 12.0 + 69.0) as u8;
 		let pitch_bend = ((note.freq / 440.0).log2() * 12.0 + 69.0) - note_number as f32;
-		self.channels[ch].current = Some(note.id);
+		self.channels[ch].current = Some(note);
 		self.channels[ch].note_number = note_number;
 		self.conn.send(&pitch_bend_msg(ch, pitch_bend))?;
-		self.conn.send(&[0x91 + ch as u8, note_number, 0x70])?;
+		self.conn.send(&[0xD1 + ch as u8, to_midi_value(note.pressure)])?; // channel pressure
+		self.conn.send(&[0xB1 + ch as u8, 74, to_midi_value(note.timbre)])?; // timbre (CC74)
+		self.conn
+			.send(&[0x91 + ch as u8, note_number, to_midi_value(note.velocity).max(1)])?;
 		Ok(())
 	}
 
@@ -218,3 +297,7 @@ fn pitch_bend_msg(ch: usize, t: f32) -> [u8; 3] {
 	let n = (t * 8191.0 / PITCH_BEND_RANGE + 8192.0) as usize;
 	[0xE1 + ch as u8, (n & 0b1111111) as u8, (n >> 7 & 0b1111111) as u8]
 }
+
+fn to_midi_value(x: f32) -> u8 {
+	(x.max(0.0).min(1.0) * 127.0).round() as u8
+}
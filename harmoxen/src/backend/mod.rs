@@ -1,9 +1,12 @@
-use crate::data::{icp, sheet::*};
+use crate::data::{icp, sheet::*, Playback, TempoMap, Track};
 
 #[derive(Debug, Clone)]
 pub enum Event {
-	SetTempo(f32),
-	PlayStart(Sheet, f32),
+	SetTempo(TempoMap),
+	PlaybackChanged(Playback),
+	PlayStart(Sheet, Vec<Track>, f32),
+	// the tracks, and the one being edited, whose target is used for ICP events
+	TracksChanged(Vec<Track>, usize),
 	PlayStop,
 	SheetChanged(Sheet),
 	ICP(icp::Event),
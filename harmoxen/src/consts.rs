@@ -1 +1,3 @@
 pub const NOTE_HEIGHT: f32 = 24.0;
+// how far, in cents, a note may be moved when attached to a root by a simple ratio
+pub const RATIO_TOLERANCE: f32 = 10.0;
@@ -3,10 +3,29 @@
 
 pub type NoteId = usize;
 
+pub const DEFAULT_VELOCITY: f32 = 0.8;
+pub const DEFAULT_PRESSURE: f32 = 0.0;
+pub const DEFAULT_TIMBRE: f32 = 0.5;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Note {
 	pub id: NoteId,
 	pub freq: f32,
+	pub velocity: f32, // 0..1, note-on strength
+	pub pressure: f32, // 0..1, MPE channel pressure
+	pub timbre: f32,   // 0..1, MPE third dimension (CC74)
+}
+
+impl Note {
+	pub fn new(id: NoteId, freq: f32) -> Note {
+		Note {
+			id,
+			freq,
+			velocity: DEFAULT_VELOCITY,
+			pressure: DEFAULT_PRESSURE,
+			timbre: DEFAULT_TIMBRE,
+		}
+	}
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -1,4 +1,5 @@
 use super::{Pattern, PatternInput};
+use crate::data::sheet::{Index, Interval};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -6,6 +7,15 @@ pub struct Marker {
 	pub at: f32,
 	pub pattern: Pattern,
 	pub pattern_input: PatternInput,
+	// note of the sheet whose frequency is the base of the pattern, instead of the typed one
+	#[serde(default)]
+	pub anchor: Option<Index>,
+	// beats over which the frequencies of the previous marker turn into these ones, 0 for an abrupt change
+	#[serde(default)]
+	pub morph: f32,
+	// interval from the base of the previous marker, which then replaces the typed base
+	#[serde(default)]
+	pub transpose: Option<Interval>,
 }
 
 impl Default for Marker {
@@ -15,6 +25,9 @@ impl Default for Marker {
 			at: 0.0,
 			pattern: input.build().unwrap(),
 			pattern_input: input,
+			anchor: None,
+			morph: 0.0,
+			transpose: None,
 		}
 	}
 }
@@ -1,5 +1,5 @@
 use crate::data::{
-	sheet::{Note, Pitch},
+	sheet::{Index, Interval, Note, Pitch, Sheet},
 	Point,
 };
 use serde::{Deserialize, Serialize};
@@ -10,6 +10,9 @@ pub use pattern::*;
 mod marker;
 pub use marker::Marker;
 
+pub mod scala;
+pub mod tun;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Layout {
 	pub markers: Vec<Marker>,
@@ -30,7 +33,11 @@ impl Layout {
 		pattern_input: PatternInput {
 			freq: freq_input::FreqInput::None,
 			time: time_input::TimeInput::None,
+			kbm: None,
 		},
+		anchor: None,
+		morph: 0.0,
+		transpose: None,
 	};
 
 	pub fn add_marker(&mut self, marker: Marker) -> usize {
@@ -50,8 +57,71 @@ impl Layout {
 		self.markers = new_markers;
 		new_idx
 	}
-	pub fn set_marker_pattern(&mut self, idx: usize, pattern: Pattern) {
-		self.markers[idx].pattern = pattern;
+	pub fn set_marker_pattern(&mut self, idx: usize, input: PatternInput) -> Result<(), LayoutParseError> {
+		self.markers[idx].pattern = input.build()?;
+		self.markers[idx].pattern_input = input;
+		Ok(())
+	}
+
+	// moves the base of markers anchored to a note or transposed from the previous marker
+	// returns whether one of them moved
+	pub fn resolve_bases(&mut self, sheet: &Sheet) -> bool {
+		let mut changed = false;
+		// in time order, so that transpositions carry through the following markers
+		let mut order: Vec<usize> = (0..self.markers.len()).collect();
+		order.sort_by(|&a, &b| self.markers[a].at.partial_cmp(&self.markers[b].at).unwrap());
+		let mut previous_base = None;
+		for idx in order {
+			let base = match (self.markers[idx].anchor, self.markers[idx].transpose) {
+				(Some(note), _) => self.anchor_freq(sheet, idx, note),
+				(None, Some(interval)) => previous_base.map(|base: f32| base * interval),
+				(None, None) => None,
+			};
+			let marker = &mut self.markers[idx];
+			previous_base = match &mut marker.pattern.freq {
+				Some(pattern) => {
+					if let Some(base) = base.filter(|&base| base != pattern.base) {
+						pattern.base = base;
+						changed = true;
+					}
+					Some(pattern.base)
+				}
+				None => None,
+			};
+		}
+		changed
+	}
+
+	// frequency of the note a marker is anchored to
+	// a note on the marker's own degrees can't move it, and a deleted one leaves the base where it was
+	fn anchor_freq(&self, sheet: &Sheet, marker: usize, idx: Index) -> Option<f32> {
+		if self.degree_root(sheet, marker, idx).is_some() {
+			return None;
+		}
+		let note = sheet.get_note(idx)?;
+		Some(sheet.get_freq(note.pitch, note.start))
+	}
+
+	// the note a note takes its frequency from, if it is on the degrees of a marker
+	// the marker's base can't follow such a note, as it would move the note along
+	pub fn degree_root(&self, sheet: &Sheet, marker: usize, idx: Index) -> Option<Index> {
+		let pitch = sheet.get_note(idx)?.pitch;
+		let root = sheet.get_root_interval(pitch).map_or(idx, |(root, _)| root);
+		let note = sheet.get_note(root)?;
+		match note.pitch {
+			Pitch::Degree { .. } if self.get_marker_idx_at(note.start) == Some(marker) => Some(root),
+			_ => None,
+		}
+	}
+
+	pub fn get_marker_idx_at(&self, at: f32) -> Option<usize> {
+		let mut closest = None;
+		for (i, marker) in self.markers.iter().enumerate() {
+			if marker.at <= at && closest.map_or(true, |c: usize| marker.at >= self.markers[c].at) {
+				closest = Some(i);
+			}
+		}
+		closest
 	}
 
 	pub fn get_marker_at(&self, at: f32, exclude: Option<usize>) -> &Marker {
@@ -64,6 +134,39 @@ impl Layout {
 		closest
 	}
 
+	// marker right before another one, which it morphs from
+	pub fn get_previous_marker(&self, marker: &Marker) -> Option<&Marker> {
+		self.markers
+			.iter()
+			.filter(|m| m.at < marker.at)
+			.max_by(|a, b| a.at.partial_cmp(&b.at).unwrap())
+	}
+
+	// frequencies in effect at some time, they are between those of two markers while the second one morphs
+	pub fn get_freq_pattern_at(&self, at: f32) -> Option<FreqMorph<'_>> {
+		let marker = self.get_marker_at(at, None);
+		let pattern = marker.pattern.freq.as_ref()?;
+		let t = (at - marker.at) / marker.morph;
+		match self.get_previous_marker(marker).and_then(|m| m.pattern.freq.as_ref()) {
+			Some(from) if t < 1.0 => Some(FreqMorph { from, to: pattern, t }),
+			_ => Some(FreqMorph::fixed(pattern)),
+		}
+	}
+
+	// period of the frequencies in effect at some time, as an interval that can be typed, an octave if there are none
+	pub fn period_at(&self, at: f32) -> Interval {
+		match self.get_freq_pattern_at(at) {
+			Some(pattern) => Interval::approximate(pattern.period(), 0.01)
+				.unwrap_or_else(|| Interval::Cents(1200.0 * pattern.period().log2())),
+			None => Interval::Ratio(2, 1),
+		}
+	}
+
+	// frequency played by a midi key or its computer keyboard equivalent at some time
+	pub fn key_freq(&self, at: f32, key: u8) -> Option<f32> {
+		self.get_marker_at(at, None).pattern.key_freq(key)
+	}
+
 	pub fn quantize_time(&self, time: f32, floor: bool) -> f32 {
 		self.quantize_time_impl(time, floor, None, None)
 	}
@@ -101,13 +204,13 @@ impl Layout {
 	}
 
 	pub fn quantize_freq(&self, at: f32, mut freq: f32) -> f32 {
-		let pattern = &self.get_marker_at(at, None).pattern;
-		if let Some(pattern) = &pattern.freq {
+		if let Some(pattern) = self.get_freq_pattern_at(at) {
 			let period = pattern.period();
-			let base = pattern.base * period.powf((freq / pattern.base).log(period).floor());
-			freq = pattern.values.iter().fold(0.0, |acc, x| {
-				if (base * x - freq).abs() < (acc - freq).abs() {
-					base * x
+			let base = pattern.base() * period.powf((freq / pattern.base()).log(period).floor());
+			freq = (0..=pattern.size() as i32).fold(0.0, |acc, i| {
+				let x = base * pattern.degree_ratio(i);
+				if (x - freq).abs() < (acc - freq).abs() {
+					x
 				} else {
 					acc
 				}
@@ -122,12 +225,164 @@ impl Layout {
 		Point::new(x, y.log2())
 	}
 
+	// notes placed on a scale are bound to its degrees, so that they follow when it is retuned
 	pub fn quantize_note(&self, mut note: Note) -> Note {
 		note.start = self.quantize_time(note.start, false);
 		note.length = self.quantize_time_after(note.start + note.length, note.start) - note.start;
 		if let Pitch::Absolute(freq) = note.pitch {
-			note.pitch = Pitch::Absolute(self.quantize_freq(note.start, freq));
+			note.pitch = self.nearest_degree(note.start, freq).unwrap_or(Pitch::Absolute(freq));
 		}
 		note
 	}
+
+	// frequency of a degree of the scale active at some time
+	// without a scale, degrees are those of 12-EDO from A4, like midi keys
+	pub fn degree_freq(&self, at: f32, step: i32, octave: i32) -> f32 {
+		match (&self.get_marker_at(at, None).pattern.freq, self.get_freq_pattern_at(at)) {
+			// while morphing, degrees are counted the same way in both scales
+			(Some(own), Some(pattern)) => pattern.base() * pattern.degree_ratio(step + octave * own.size() as i32),
+			_ => 440.0 * 2f32.powf(step as f32 / 12.0 + octave as f32),
+		}
+	}
+
+	// degree of the scale active at some time that a frequency is on, None if it is between degrees
+	pub fn degree_on(&self, at: f32, freq: f32) -> Option<Pitch> {
+		match self.nearest_degree(at, freq)? {
+			Pitch::Degree { step, octave } if (self.degree_freq(at, step, octave) / freq).log2().abs() < 1e-4 => {
+				Some(Pitch::Degree { step, octave })
+			}
+			_ => None,
+		}
+	}
+
+	// degree of the scale active at some time closest to a frequency, None if there is no scale
+	pub fn nearest_degree(&self, at: f32, freq: f32) -> Option<Pitch> {
+		let own = self.get_marker_at(at, None).pattern.freq.as_ref()?;
+		let pattern = self.get_freq_pattern_at(at)?;
+		if !(freq > 0.0) {
+			return None;
+		}
+		let period = pattern.period();
+		let n = pattern.size();
+		let octave = (freq / pattern.base()).log(period).floor();
+		let base = pattern.base() * period.powf(octave);
+		let step = (0..n + 1)
+			.min_by(|&a, &b| {
+				let dist = |i: usize| (base * pattern.degree_ratio(i as i32) - freq).abs();
+				dist(a).partial_cmp(&dist(b)).unwrap()
+			})
+			.unwrap();
+		// the last value is the first degree of the next period, a morphing pattern may be longer than the marker's
+		let degree = step as i32 + octave as i32 * n as i32;
+		let own_n = own.size() as i32;
+		Some(Pitch::Degree {
+			step: degree.rem_euclid(own_n),
+			octave: degree.div_euclid(own_n),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn anchors_to_notes_off_the_markers_degrees() {
+		let mut sheet = Sheet::default();
+		// a C5 in the default 12-EDO from A4
+		let a = sheet.add_note(Note {
+			pitch: Pitch::Degree { step: 3, octave: 0 },
+			..Note::new(Point::new(0.0, 0.0), 1.0)
+		});
+		let b = sheet.add_note(Note {
+			pitch: Pitch::Relative(a, Interval::Ratio(3, 2)),
+			..Note::new(Point::new(4.0, 0.0), 1.0)
+		});
+		let mut layout = sheet.layout().clone();
+		layout.add_marker(Marker {
+			at: 4.0,
+			..Marker::default()
+		});
+		assert_eq!(layout.degree_root(&sheet, 0, a), Some(a));
+		assert_eq!(layout.degree_root(&sheet, 0, b), Some(a));
+		assert_eq!(layout.degree_root(&sheet, 1, b), None);
+
+		// the first marker can't follow its own degree
+		layout.markers[0].anchor = Some(b);
+		assert!(!layout.resolve_bases(&sheet));
+		assert_eq!(layout.markers[0].pattern.freq.as_ref().unwrap().base, 440.0);
+
+		// the second one can
+		layout.markers[0].anchor = None;
+		layout.markers[1].anchor = Some(b);
+		assert!(layout.resolve_bases(&sheet));
+		let base = layout.markers[1].pattern.freq.as_ref().unwrap().base;
+		assert!((base / (440.0 * 2f32.powf(3.0 / 12.0) * 1.5)).log2().abs() < 1e-6);
+		assert!(!layout.resolve_bases(&sheet));
+	}
+
+	#[test]
+	fn finds_degrees_of_the_scale() {
+		let mut layout = Layout::default();
+		let close = |a: f32, b: f32| (a / b).log2().abs() < 1e-6;
+		assert!(close(layout.degree_freq(0.0, 3, -1), 440.0 * 2f32.powf(3.0 / 12.0 - 1.0)));
+		assert_eq!(
+			layout.nearest_degree(0.0, 440.0 * 2f32.powf(-13.0 / 12.0) * 1.01),
+			Some(Pitch::Degree { step: 11, octave: -2 })
+		);
+		assert_eq!(layout.degree_on(0.0, 880.0), Some(Pitch::Degree { step: 0, octave: 1 }));
+		assert_eq!(layout.degree_on(0.0, 450.0), None);
+		assert_eq!(layout.nearest_degree(0.0, 0.0), None);
+
+		// placed notes are bound to the nearest degree, if there is a scale
+		let note = layout.quantize_note(Note::new(Point::new(0.0, 9.0), 1.0));
+		assert_eq!(note.pitch, Pitch::Degree { step: 3, octave: 0 });
+		layout.markers[0].pattern.freq = None;
+		assert_eq!(layout.nearest_degree(0.0, 512.0), None);
+		let note = layout.quantize_note(Note::new(Point::new(0.0, 9.0), 1.0));
+		assert_eq!(note.pitch, Pitch::Absolute(512.0));
+		// degrees still mean those of 12-EDO
+		assert_eq!(layout.degree_freq(0.0, 0, 1), 880.0);
+		assert_eq!(layout.period_at(0.0), Interval::Ratio(2, 1));
+	}
+
+	#[test]
+	fn finds_degrees_while_morphing() {
+		let mut layout = Layout::default();
+		let idx = layout.add_marker(Marker {
+			at: 4.0,
+			morph: 4.0,
+			..Marker::default()
+		});
+		let input = PatternInput {
+			freq: freq_input::FreqInput::Equal {
+				base: "220".into(),
+				ndiv: "6".into(),
+				interval: "2".into(),
+			},
+			..PatternInput::default()
+		};
+		layout.set_marker_pattern(idx, input).unwrap();
+		let close = |a: f32, b: f32| (a / b).log2().abs() < 1e-5;
+		assert_eq!(layout.period_at(2.0), Interval::Ratio(2, 1));
+		assert_eq!(layout.degree_freq(2.0, 1, 0), 440.0 * 2f32.powf(1.0 / 12.0));
+		assert_eq!(layout.degree_freq(8.0, 1, 0), 220.0 * 2f32.powf(1.0 / 6.0));
+		// halfway, each degree is between its frequencies in both scales, counted in the new one
+		let base = 440.0 / 2f32.sqrt();
+		assert!(close(layout.degree_freq(6.0, 0, 0), base));
+		assert!(close(
+			layout.degree_freq(6.0, 1, 0),
+			base * 2f32.powf((1.0 / 12.0 + 1.0 / 6.0) / 2.0)
+		));
+		assert!(close(layout.degree_freq(6.0, 0, 1), base * 2f32.powf((0.5 + 1.0) / 2.0)));
+		assert!(close(
+			layout.degree_freq(6.0, 2, -1),
+			base * 2f32.powf((-4.0 / 12.0 - 4.0 / 6.0) / 2.0)
+		));
+		for &(step, octave) in &[(0, 0), (1, 0), (5, 0), (3, 1), (2, -1)] {
+			let freq = layout.degree_freq(6.0, step, octave);
+			assert_eq!(layout.nearest_degree(6.0, freq), Some(Pitch::Degree { step, octave }));
+			assert!(close(layout.quantize_freq(6.0, freq * 1.001), freq));
+		}
+	}
 }
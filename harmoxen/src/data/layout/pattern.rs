@@ -1,3 +1,4 @@
+use super::scala::Kbm;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -28,6 +29,60 @@ impl FreqPattern {
 	pub fn period(&self) -> f32 {
 		self.values[self.values.len() - 1] / self.values[0]
 	}
+
+	// number of degrees in a period
+	pub fn size(&self) -> usize {
+		self.values.len() - 1
+	}
+
+	// ratio to the base of a degree, the degrees after the last value continue in the next period
+	pub fn degree_ratio(&self, degree: i32) -> f32 {
+		let n = self.size() as i32;
+		self.values[degree.rem_euclid(n) as usize] * self.period().powi(degree.div_euclid(n))
+	}
+}
+
+// frequencies of a pattern morphing from another one, each degree moving in log-frequency as t goes from 0 to 1
+// they repeat after a common multiple of both sizes, so degrees are computed when needed rather than stored
+#[derive(Clone, Copy, Debug)]
+pub struct FreqMorph<'a> {
+	pub from: &'a FreqPattern,
+	pub to: &'a FreqPattern,
+	pub t: f32,
+}
+
+impl<'a> FreqMorph<'a> {
+	// a pattern that isn't morphing
+	pub fn fixed(pattern: &'a FreqPattern) -> FreqMorph<'a> {
+		FreqMorph {
+			from: pattern,
+			to: pattern,
+			t: 1.0,
+		}
+	}
+
+	pub fn base(&self) -> f32 {
+		self.from.base.powf(1.0 - self.t) * self.to.base.powf(self.t)
+	}
+
+	pub fn size(&self) -> usize {
+		let (a, b) = (self.from.size(), self.to.size());
+		let (mut x, mut y) = (a, b);
+		while y != 0 {
+			let r = x % y;
+			x = y;
+			y = r;
+		}
+		a / x * b
+	}
+
+	pub fn period(&self) -> f32 {
+		self.degree_ratio(self.size() as i32) / self.degree_ratio(0)
+	}
+
+	pub fn degree_ratio(&self, degree: i32) -> f32 {
+		self.from.degree_ratio(degree).powf(1.0 - self.t) * self.to.degree_ratio(degree).powf(self.t)
+	}
 }
 
 // the elements of each component are assumed to be sorted
@@ -35,16 +90,33 @@ impl FreqPattern {
 pub struct Pattern {
 	pub time: Option<TimePattern>,
 	pub freq: Option<FreqPattern>,
+	#[serde(default)]
+	pub keyboard: Option<Kbm>,
 }
 
 impl Pattern {
-	pub const EMPTY: Pattern = Pattern { time: None, freq: None };
+	pub const EMPTY: Pattern = Pattern {
+		time: None,
+		freq: None,
+		keyboard: None,
+	};
+
+	// frequency played by a midi key, keys map to consecutive degrees from A4 if there is no keyboard mapping
+	pub fn key_freq(&self, key: u8) -> Option<f32> {
+		let freq = self.freq.as_ref()?;
+		let degree = match &self.keyboard {
+			Some(kbm) => kbm.degree(key)?,
+			None => key as i32 - 69,
+		};
+		Some(freq.base * freq.degree_ratio(degree))
+	}
 }
 
 //
 
 use derive_more::Display;
 use std::error::Error;
+use std::str::FromStr;
 
 pub mod time_input;
 use time_input::TimeInput;
@@ -55,43 +127,103 @@ use freq_input::FreqInput;
 pub struct PatternInput {
 	pub time: TimeInput,
 	pub freq: FreqInput,
+	#[serde(default)]
+	pub kbm: Option<String>, // the whole .kbm file, like the .scl of the Scala mode
 }
 impl Default for PatternInput {
 	fn default() -> PatternInput {
 		PatternInput {
 			time: Default::default(),
 			freq: Default::default(),
+			kbm: None,
 		}
 	}
 }
 
 impl PatternInput {
 	pub fn build(&self) -> Result<Pattern, LayoutParseError> {
+		let mut freq = self.freq.build()?;
+		let keyboard = self.kbm.as_ref().map(|text| Kbm::parse(text)).transpose()?;
+		// the keyboard mapping decides which frequency the base is at
+		if let (Some(freq), Some(kbm)) = (&mut freq, &keyboard) {
+			let degree = kbm
+				.degree(kbm.reference)
+				.ok_or_else(|| LayoutParseError::field(".kbm file", "the reference key is not mapped"))?;
+			freq.base = kbm.freq / freq.degree_ratio(degree);
+		}
 		Ok(Pattern {
 			time: self.time.build()?,
-			freq: self.freq.build()?,
+			freq,
+			keyboard,
 		})
 	}
 }
 
 // Errors
 
-#[derive(Debug, Display)]
-pub struct LayoutParseError;
+#[derive(Debug, Display, Clone, PartialEq)]
+pub enum LayoutParseError {
+	#[display(fmt = "{}: {}", _0, _1)]
+	Field(&'static str, String), // the field that couldn't be used, and why
+	#[display(fmt = "{}: gives {} for i = {}", _0, _2, _1)]
+	Eval(&'static str, usize, f32), // a formula giving an unusable value
+}
 impl Error for LayoutParseError {}
 
-impl From<std::num::ParseIntError> for LayoutParseError {
-	fn from(_: std::num::ParseIntError) -> LayoutParseError {
-		LayoutParseError
+impl LayoutParseError {
+	pub fn field(name: &'static str, reason: impl ToString) -> LayoutParseError {
+		LayoutParseError::Field(name, reason.to_string())
 	}
 }
-impl From<std::num::ParseFloatError> for LayoutParseError {
-	fn from(_: std::num::ParseFloatError) -> LayoutParseError {
-		LayoutParseError
-	}
+
+// reads the text of a field, naming it if it can't be read
+pub fn parse_field<T>(name: &'static str, text: &str) -> Result<T, LayoutParseError>
+where
+	T: FromStr,
+	T::Err: std::fmt::Display,
+{
+	text.trim().parse::<T>().map_err(|e| LayoutParseError::field(name, e))
 }
-impl From<meval::Error> for LayoutParseError {
-	fn from(_: meval::Error) -> LayoutParseError {
-		LayoutParseError
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn morphs_degree_by_degree() {
+		let from = FreqPattern::new(440.0, vec![1.0, 1.5, 2.0]);
+		let to = FreqPattern::new(220.0, vec![1.0, 1.25, 1.5, 2.0]);
+		let close = |a: f32, b: f32| (a / b).log2().abs() < 1e-6;
+		let fixed = FreqMorph::fixed(&to);
+		assert_eq!((fixed.base(), fixed.size(), fixed.period()), (220.0, 3, 2.0));
+		for i in -5..10 {
+			assert_eq!(fixed.degree_ratio(i), to.degree_ratio(i));
+		}
+
+		let morph = FreqMorph {
+			from: &from,
+			to: &to,
+			t: 0.5,
+		};
+		assert!(close(morph.base(), 440.0 / 2f32.sqrt()));
+		assert_eq!(morph.size(), 6);
+		// 3 periods of the first pattern and 2 of the second
+		assert!(close(morph.period(), 32f32.sqrt()));
+		for i in -7..14 {
+			assert!(close(
+				morph.degree_ratio(i),
+				(from.degree_ratio(i) * to.degree_ratio(i)).sqrt()
+			));
+		}
+		// the ends are the patterns themselves
+		let start = FreqMorph {
+			from: &from,
+			to: &to,
+			t: 0.0,
+		};
+		assert_eq!(
+			(start.base(), start.degree_ratio(1), start.degree_ratio(-1)),
+			(440.0, 1.5, 0.75)
+		);
 	}
 }
@@ -1,8 +1,9 @@
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
 
-use super::LayoutParseError;
-use crate::data::layout::FreqPattern;
+use super::{parse_field, LayoutParseError};
+use crate::data::layout::{scala::Scl, FreqPattern};
+use crate::data::sheet::Interval;
 
 #[derive(Debug, Display, Clone, PartialEq, Eq)]
 pub enum Mode {
@@ -10,6 +11,13 @@ pub enum Mode {
 	Equal,
 	Enumeration,
 	HarmonicSegment,
+	Scala,
+	Generated,
+	SubharmonicSegment,
+	Cps,
+	Diamond,
+	Formula,
+	Intervals,
 }
 
 #[derive(Clone, Debug, Display, Deserialize, Serialize)]
@@ -22,6 +30,42 @@ pub enum FreqInput {
 	Enumeration { base: String, values: String },
 	#[display(fmt = "Harmonic Segment")]
 	HarmonicSegment { base: String, from: String, to: String },
+	#[display(fmt = "Scala")]
+	Scala { base: String, scl: String }, // the whole .scl file, so that projects don't depend on it
+	#[display(fmt = "Generated")]
+	Generated {
+		base: String,
+		period: String,
+		generator: String,
+		size: String,
+		down: String, // number of generators stacked below the base
+	},
+	#[display(fmt = "Subharmonic Segment")]
+	SubharmonicSegment { base: String, from: String, to: String },
+	#[display(fmt = "CPS")]
+	Cps {
+		base: String,
+		factors: String,
+		choose: String,
+		#[serde(default = "default_period")]
+		period: String,
+	},
+	#[display(fmt = "Diamond")]
+	Diamond {
+		base: String,
+		limit: String,
+		#[serde(default = "default_period")]
+		period: String,
+	},
+	#[display(fmt = "Formula")]
+	Formula {
+		base: String,
+		count: String,
+		period: String,
+		formula: String, // ratio of each degree i after the first
+	},
+	#[display(fmt = "Intervals")]
+	Intervals { base: String, values: String }, // degrees after the unison, the last one is the period
 }
 
 impl Default for FreqInput {
@@ -58,6 +102,61 @@ impl FreqInput {
 			to: "16".into(),
 		}
 	}
+	pub fn default_scala() -> Self {
+		Self::Scala {
+			base: "440".into(),
+			scl: DEFAULT_SCL.into(),
+		}
+	}
+
+	pub fn default_generated() -> Self {
+		Self::Generated {
+			base: "440".into(),
+			period: "2".into(),
+			generator: "3/2".into(),
+			size: "7".into(),
+			down: "1".into(),
+		}
+	}
+
+	pub fn default_subharmonic_segment() -> Self {
+		Self::SubharmonicSegment {
+			base: "440".into(),
+			from: "8".into(),
+			to: "16".into(),
+		}
+	}
+	pub fn default_cps() -> Self {
+		Self::Cps {
+			base: "440".into(),
+			factors: "1:3:5:7".into(),
+			choose: "2".into(),
+			period: "2".into(),
+		}
+	}
+	pub fn default_diamond() -> Self {
+		Self::Diamond {
+			base: "440".into(),
+			limit: "5".into(),
+			period: "2".into(),
+		}
+	}
+
+	pub fn default_formula() -> Self {
+		Self::Formula {
+			base: "440".into(),
+			count: "13".into(),
+			period: "2".into(),
+			formula: "2^(i/13) * (1 + 0.01*sin(i))".into(),
+		}
+	}
+
+	pub fn default_intervals() -> Self {
+		Self::Intervals {
+			base: "440".into(),
+			values: "9/8 5/4 4/3 3/2 5/3 15/8 2/1".into(),
+		}
+	}
 
 	pub fn mode(&self) -> Mode {
 		match self {
@@ -65,6 +164,13 @@ impl FreqInput {
 			FreqInput::Equal { .. } => Mode::Equal,
 			FreqInput::Enumeration { .. } => Mode::Enumeration,
 			FreqInput::HarmonicSegment { .. } => Mode::HarmonicSegment,
+			FreqInput::Scala { .. } => Mode::Scala,
+			FreqInput::Generated { .. } => Mode::Generated,
+			FreqInput::SubharmonicSegment { .. } => Mode::SubharmonicSegment,
+			FreqInput::Cps { .. } => Mode::Cps,
+			FreqInput::Diamond { .. } => Mode::Diamond,
+			FreqInput::Formula { .. } => Mode::Formula,
+			FreqInput::Intervals { .. } => Mode::Intervals,
 		}
 	}
 
@@ -72,23 +178,28 @@ impl FreqInput {
 		match self.clone() {
 			FreqInput::None => Ok(None),
 			FreqInput::Equal { base, ndiv, interval } => {
-				let base = base.parse::<f32>()?;
-				let ndiv = ndiv.parse::<usize>()?;
-				let interval = interval.parse::<f32>()?;
+				let base = parse_base(&base)?;
+				let ndiv = parse_field::<usize>("# divisions", &ndiv)?;
+				let interval = parse_field::<f32>("interval", &interval)?;
+				if ndiv == 0 {
+					return Err(LayoutParseError::field("# divisions", "must be at least 1"));
+				}
+				if interval <= 1.0 {
+					return Err(LayoutParseError::field("interval", "must be greater than 1"));
+				}
 				Ok(Some(FreqPattern::new(
 					base,
 					(0..ndiv + 1).map(|k| interval.powf(k as f32 / ndiv as f32)).collect(),
 				)))
 			}
 			FreqInput::Enumeration { base, values } => {
-				let base = base.parse::<f32>()?;
+				let base = parse_base(&base)?;
 				let values = values
 					.split(":")
-					.map(|x| x.parse::<usize>())
-					.collect::<Result<Vec<usize>, _>>()
-					.map_err(|_| super::LayoutParseError)?;
-				if values.len() == 0 {
-					return Err(LayoutParseError);
+					.map(|x| parse_field::<usize>("values", x))
+					.collect::<Result<Vec<usize>, _>>()?;
+				if values.len() < 2 || values.contains(&0) {
+					return Err(LayoutParseError::field("values", "needs at least two positive values"));
 				}
 
 				let first = values[0] as f32;
@@ -96,15 +207,388 @@ impl FreqInput {
 				Ok(Some(FreqPattern::new(base, values)))
 			}
 			FreqInput::HarmonicSegment { base, from, to } => {
-				let base = base.parse::<f32>()?;
-				let from = from.parse::<usize>()?;
-				let to = to.parse::<usize>()?;
-				if from >= to {
-					return Err(LayoutParseError);
+				let base = parse_base(&base)?;
+				let from = parse_field::<usize>("from", &from)?;
+				let to = parse_field::<usize>("to", &to)?;
+				if from == 0 || from >= to {
+					return Err(LayoutParseError::field("to", "must be greater than from"));
 				}
 				let values = (from..to + 1).map(|x| x as f32 / from as f32).collect::<Vec<f32>>();
 				Ok(Some(FreqPattern::new(base, values)))
 			}
+			FreqInput::Intervals { base, values } => {
+				let base = parse_base(&base)?;
+				let intervals = values
+					.split_whitespace()
+					.map(|x| match x.parse::<Interval>() {
+						Ok(interval) if interval.to_f32() > 0.0 && interval.to_f32().is_finite() => Ok(interval),
+						_ => Err(LayoutParseError::field("intervals", format!("{} is not an interval", x))),
+					})
+					.collect::<Result<Vec<Interval>, _>>()?;
+				match intervals.last() {
+					Some(period) if period.to_f32() > 1.0 => {}
+					_ => {
+						return Err(LayoutParseError::field(
+							"intervals",
+							"the last one is the period, it must be above the unison",
+						))
+					}
+				}
+				let mut values = vec![1.0];
+				values.extend(intervals.iter().map(|x| x.to_f32()));
+				let mut pattern = FreqPattern::new(base, values);
+				pattern.values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+				Ok(Some(pattern))
+			}
+			FreqInput::Scala { base, scl } => {
+				let base = parse_base(&base)?;
+				let scl = Scl::parse(&scl)?;
+				if scl.period() <= 1.0 {
+					return Err(LayoutParseError::field(
+						".scl file",
+						"the last degree must be above the unison",
+					));
+				}
+				let mut values = vec![1.0];
+				values.extend(scl.degrees);
+				let mut pattern = FreqPattern::new(base, values);
+				pattern.values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+				Ok(Some(pattern))
+			}
+			FreqInput::Generated {
+				base,
+				period,
+				generator,
+				size,
+				down,
+			} => {
+				let base = parse_base(&base)?;
+				let (period, generator) = parse_generator(&period, &generator)?;
+				let size = parse_field::<usize>("# notes", &size)?;
+				let down = parse_field::<usize>("# generators down", &down)?;
+				if size == 0 {
+					return Err(LayoutParseError::field("# notes", "must be at least 1"));
+				}
+				if down >= size {
+					return Err(LayoutParseError::field(
+						"# generators down",
+						"must be less than the number of notes",
+					));
+				}
+				let values = (0..size).map(|k| generator.powi(k as i32 - down as i32)).collect();
+				Ok(Some(FreqPattern::new(base, reduce(values, period))))
+			}
+			FreqInput::SubharmonicSegment { base, from, to } => {
+				let base = parse_base(&base)?;
+				let from = parse_field::<usize>("from", &from)?;
+				let to = parse_field::<usize>("to", &to)?;
+				if from == 0 || from >= to {
+					return Err(LayoutParseError::field("to", "must be greater than from"));
+				}
+				let values = (from..to + 1).rev().map(|x| to as f32 / x as f32).collect::<Vec<f32>>();
+				Ok(Some(FreqPattern::new(base, values)))
+			}
+			FreqInput::Cps {
+				base,
+				factors,
+				choose,
+				period,
+			} => {
+				let base = parse_base(&base)?;
+				let period = parse_period(&period)?;
+				let factors = factors
+					.split(":")
+					.map(|x| parse_field::<u64>("factors", x))
+					.collect::<Result<Vec<u64>, _>>()?;
+				let choose = parse_field::<usize>("# factors per product", &choose)?;
+				if factors.contains(&0) {
+					return Err(LayoutParseError::field("factors", "must be positive"));
+				}
+				if choose == 0 || choose > factors.len() {
+					return Err(LayoutParseError::field(
+						"# factors per product",
+						"must be between 1 and the number of factors",
+					));
+				}
+				let products = combination_products(&factors, choose)?;
+				// the smallest product is the 1/1
+				let first = *products.iter().min().unwrap() as f32;
+				let values = products.iter().map(|&x| x as f32 / first).collect();
+				Ok(Some(FreqPattern::new(base, reduce(values, period))))
+			}
+			FreqInput::Diamond { base, limit, period } => {
+				let base = parse_base(&base)?;
+				let period = parse_period(&period)?;
+				let limit = parse_field::<usize>("odd limit", &limit)?;
+				if limit == 0 {
+					return Err(LayoutParseError::field("odd limit", "must be at least 1"));
+				}
+				let odds = (1..limit + 1).step_by(2).collect::<Vec<usize>>();
+				let values = odds
+					.iter()
+					.flat_map(|&a| odds.iter().map(move |&b| a as f32 / b as f32))
+					.collect();
+				Ok(Some(FreqPattern::new(base, reduce(values, period))))
+			}
+			FreqInput::Formula {
+				base,
+				count,
+				period,
+				formula,
+			} => {
+				let base = parse_base(&base)?;
+				let count = parse_field::<usize>("# notes", &count)?;
+				let period = parse_period(&period)?;
+				if count == 0 {
+					return Err(LayoutParseError::field("# notes", "must be at least 1"));
+				}
+				let expr = parse_field::<meval::Expr>("formula", &formula)?;
+				let func = expr.bind("i").map_err(|e| LayoutParseError::field("formula", e))?;
+				// the first degree is always the 1/1, the formula gives the others
+				let mut values = vec![1.0];
+				for i in 1..count {
+					let x = func(i as f64) as f32;
+					if !(x > 0.0 && x.is_finite()) {
+						return Err(LayoutParseError::Eval("formula", i, x));
+					}
+					values.push(x);
+				}
+				Ok(Some(FreqPattern::new(base, reduce(values, period))))
+			}
 		}
 	}
 }
+
+fn parse_base(text: &str) -> Result<f32, LayoutParseError> {
+	match parse_field::<f32>("base frequency", text)? {
+		base if base > 0.0 && base.is_finite() => Ok(base),
+		_ => Err(LayoutParseError::field("base frequency", "must be positive")),
+	}
+}
+
+// brings ratios into the period, without duplicates, and closes the scale with the period itself
+fn reduce(values: Vec<f32>, period: f32) -> Vec<f32> {
+	let mut values = values
+		.into_iter()
+		.map(|x| x / period.powf((x.log(period) + 1e-6).floor()))
+		.collect::<Vec<f32>>();
+	values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+	values.dedup_by(|a, b| (*a / *b).log2().abs() < 1e-6);
+	values.push(period);
+	values
+}
+
+// products of every way to pick `k` of the factors
+fn combination_products(factors: &[u64], k: usize) -> Result<Vec<u64>, LayoutParseError> {
+	if k == 0 {
+		return Ok(vec![1]);
+	}
+	let mut products = vec![];
+	for i in 0..factors.len() + 1 - k {
+		for x in combination_products(&factors[i + 1..], k - 1)? {
+			products.push(
+				x.checked_mul(factors[i])
+					.ok_or_else(|| LayoutParseError::field("factors", "their products are too large"))?,
+			);
+		}
+	}
+	Ok(products)
+}
+
+fn default_period() -> String {
+	"2".into()
+}
+
+// the period can be written as an expression, like 3/2 or 2^(1/2)
+fn parse_period(text: &str) -> Result<f32, LayoutParseError> {
+	match meval::eval_str(text).map_err(|e| LayoutParseError::field("period", e))? as f32 {
+		period if period > 1.0 && period.is_finite() => Ok(period),
+		_ => Err(LayoutParseError::field("period", "must be greater than 1")),
+	}
+}
+
+// period and generator can be written as expressions, like 3/2 or 2^(7/12)
+pub fn parse_generator(period: &str, generator: &str) -> Result<(f32, f32), LayoutParseError> {
+	let period = parse_period(period)?;
+	let generator = meval::eval_str(generator).map_err(|e| LayoutParseError::field("generator", e))? as f32;
+	if !(generator > 0.0 && generator.is_finite()) {
+		return Err(LayoutParseError::field("generator", "must be positive"));
+	}
+	Ok((period, generator))
+}
+
+// sizes up to `max` for which stacking the generator gives a moment of symmetry
+// each one is found from the previous one by splitting its large steps into a small one and the remainder
+pub fn mos_sizes(period: f32, generator: f32, max: usize) -> Vec<usize> {
+	let g = generator.log(period).rem_euclid(1.0) as f64;
+	let mut sizes = vec![];
+	let (mut large, mut small) = (g.max(1.0 - g), g.min(1.0 - g));
+	let (mut n_large, mut n_small) = (1, 1);
+	while small > 1e-6 && n_large + n_small <= max {
+		sizes.push(n_large + n_small);
+		if large - small < 1e-6 {
+			break; // every step is the same, the scale is an equal division of the period
+		}
+		let rest = large - small;
+		let (n_s, n_rest) = (n_large + n_small, n_large);
+		if rest > small {
+			large = rest;
+			n_large = n_rest;
+			n_small = n_s;
+		} else {
+			large = small;
+			small = rest;
+			n_large = n_s;
+			n_small = n_rest;
+		}
+	}
+	sizes
+}
+
+const DEFAULT_SCL: &str = "! major.scl
+!
+5-limit major scale
+ 7
+!
+ 9/8
+ 5/4
+ 4/3
+ 3/2
+ 5/3
+ 15/8
+ 2/1
+";
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mos_sizes_of_the_fifth() {
+		assert_eq!(mos_sizes(2.0, 1.5, 20), vec![2, 3, 5, 7, 12, 17]);
+		assert_eq!(mos_sizes(2.0, 1.5, 60), vec![2, 3, 5, 7, 12, 17, 29, 41, 53]);
+		// the generator is reduced into the period, and its inverse gives the same scales
+		assert_eq!(mos_sizes(2.0, 3.0, 20), vec![2, 3, 5, 7, 12, 17]);
+		assert_eq!(mos_sizes(2.0, 4.0 / 3.0, 20), vec![2, 3, 5, 7, 12, 17]);
+	}
+
+	#[test]
+	fn mos_sizes_stop_at_equal_divisions() {
+		assert_eq!(mos_sizes(2.0, 2f32.powf(7.0 / 12.0), 100), vec![2, 3, 5, 7, 12]);
+		assert_eq!(mos_sizes(2.0, 2f32.powf(1.0 / 3.0), 100), vec![2, 3]);
+		assert_eq!(mos_sizes(2.0, 2.0, 100), Vec::<usize>::new());
+	}
+
+	#[test]
+	fn mos_sizes_in_other_periods() {
+		// bohlen-pierce, a 3/1 period with a 9/7 generator
+		let sizes = mos_sizes(3.0, 9.0 / 7.0, 13);
+		assert_eq!(sizes.last(), Some(&13));
+		assert!(sizes.windows(2).all(|w| w[0] < w[1]));
+	}
+
+	#[test]
+	fn combination_products_pick_each_subset_once() {
+		assert_eq!(combination_products(&[1, 3, 5, 7], 1).unwrap(), vec![1, 3, 5, 7]);
+		assert_eq!(combination_products(&[1, 3, 5, 7], 2).unwrap(), vec![3, 5, 7, 15, 21, 35]);
+		assert_eq!(combination_products(&[1, 3, 5, 7], 4).unwrap(), vec![105]);
+		assert_eq!(combination_products(&[1, 3, 5, 7, 9, 11], 3).unwrap().len(), 20);
+		assert!(combination_products(&[1 << 40, 1 << 30, 3], 2).is_err());
+	}
+
+	#[test]
+	fn enumerations_need_positive_values() {
+		let enumeration = |values: &str| FreqInput::Enumeration {
+			base: "100".to_string(),
+			values: values.to_string(),
+		};
+		assert_eq!(
+			enumeration("4:5:6:8").build().unwrap().unwrap().values,
+			vec![1.0, 1.25, 1.5, 2.0]
+		);
+		for values in &["4", "0:5:8", "4:0:5", "4:5:0", "4:-5:8"] {
+			assert!(enumeration(values).build().is_err(), "{}", values);
+		}
+	}
+
+	#[test]
+	fn intervals_must_be_positive() {
+		let intervals = |values: &str| FreqInput::Intervals {
+			base: "100".to_string(),
+			values: values.to_string(),
+		};
+		let values = intervals("5/4 700.0c 2/1").build().unwrap().unwrap().values;
+		assert_eq!(values.len(), 4);
+		assert_eq!((values[1], values[3]), (1.25, 2.0));
+		for values in &["0/1 2/1", "3/0 2/1", "inf 2/1", "NaNc 2/1", "-1.5 2/1", "5/4 1/2", ""] {
+			assert!(intervals(values).build().is_err(), "{}", values);
+		}
+	}
+
+	#[test]
+	fn hexany_and_diamond() {
+		let hexany = FreqInput::Cps {
+			base: "100".to_string(),
+			factors: "1:3:5:7".to_string(),
+			choose: "2".to_string(),
+			period: "2".to_string(),
+		};
+		let values = hexany.build().unwrap().unwrap().values;
+		let expected = [1.0, 7.0 / 6.0, 5.0 / 4.0, 35.0 / 24.0, 5.0 / 3.0, 7.0 / 4.0, 2.0];
+		assert_eq!(values.len(), expected.len());
+		for (a, b) in values.iter().zip(&expected) {
+			assert!((a - b).abs() < 1e-5);
+		}
+		let diamond = FreqInput::Diamond {
+			base: "100".to_string(),
+			limit: "5".to_string(),
+			period: "2".to_string(),
+		};
+		let values = diamond.build().unwrap().unwrap().values;
+		let expected = [1.0, 6.0 / 5.0, 5.0 / 4.0, 4.0 / 3.0, 3.0 / 2.0, 8.0 / 5.0, 5.0 / 3.0, 2.0];
+		assert_eq!(values.len(), expected.len());
+		for (a, b) in values.iter().zip(&expected) {
+			assert!((a - b).abs() < 1e-5);
+		}
+	}
+
+	#[test]
+	fn cps_and_diamond_in_other_periods() {
+		let tritave = FreqInput::Diamond {
+			base: "100".to_string(),
+			limit: "3".to_string(),
+			period: "3".to_string(),
+		};
+		assert_eq!(tritave.build().unwrap().unwrap().values, vec![1.0, 3.0]);
+		let hexany = FreqInput::Cps {
+			base: "100".to_string(),
+			factors: "1:5:7:11".to_string(),
+			choose: "2".to_string(),
+			period: "3".to_string(),
+		};
+		let values = hexany.build().unwrap().unwrap().values;
+		assert_eq!(values.len(), 7);
+		assert_eq!(values.last(), Some(&3.0));
+		assert!(values.iter().all(|&x| x >= 1.0 && x <= 3.0));
+		let flat = FreqInput::Cps {
+			base: "100".to_string(),
+			factors: "1:3:5:7".to_string(),
+			choose: "2".to_string(),
+			period: "1".to_string(),
+		};
+		assert!(flat.build().is_err());
+		let huge = FreqInput::Cps {
+			base: "100".to_string(),
+			factors: "4294967296:4294967296:3".to_string(),
+			choose: "2".to_string(),
+			period: "2".to_string(),
+		};
+		assert!(huge.build().is_err());
+	}
+
+	#[test]
+	fn reads_cps_and_diamond_without_a_period() {
+		let input: FreqInput = ron::de::from_str("Diamond(base: \"100\", limit: \"5\")").unwrap();
+		assert_eq!(input.build().unwrap().unwrap().period(), 2.0);
+	}
+}
@@ -1,7 +1,7 @@
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
 
-use super::LayoutParseError;
+use super::{parse_field, LayoutParseError};
 use crate::data::layout::TimePattern;
 
 #[derive(Debug, Display, Clone, PartialEq, Eq)]
@@ -70,29 +70,37 @@ impl TimeInput {
 		match self.clone() {
 			TimeInput::None => Ok(None),
 			TimeInput::Regular { ndiv, nbeats } => {
-				let ndiv = ndiv.parse::<usize>()?;
-				let nbeats = nbeats.parse::<usize>()?;
+				let ndiv = parse_field::<usize>("# divisions", &ndiv)?;
+				let nbeats = parse_field::<usize>("# beats", &nbeats)?;
 				Ok(Some(TimePattern {
 					values: (0..ndiv).map(|k| k as f32 / ndiv as f32).collect(),
 					nbeats,
 				}))
 			}
 			TimeInput::Formula { ndiv, nbeats, formula } => {
-				let ndiv = ndiv.parse::<usize>()?;
-				let nbeats = nbeats.parse::<usize>()?;
-				let expr: meval::Expr = formula.parse()?;
-				let func = expr.bind("i").map_err(|_| LayoutParseError)?;
-				Ok(Some(TimePattern {
-					values: (0..ndiv).map(|i| func(i as f64) as f32).collect(),
-					nbeats,
-				}))
+				let ndiv = parse_field::<usize>("# divisions", &ndiv)?;
+				let nbeats = parse_field::<usize>("# beats", &nbeats)?;
+				let expr = parse_field::<meval::Expr>("formula", &formula)?;
+				let func = expr.bind("i").map_err(|e| LayoutParseError::field("formula", e))?;
+				let mut values = vec![];
+				for i in 0..ndiv {
+					let x = func(i as f64) as f32;
+					if !x.is_finite() {
+						return Err(LayoutParseError::Eval("formula", i, x));
+					}
+					values.push(x);
+				}
+				Ok(Some(TimePattern { values, nbeats }))
 			}
 			TimeInput::Poly { ndiv0, ndiv1, nbeats } => {
-				let ndiv0 = ndiv0.parse::<usize>()?;
-				let ndiv1 = ndiv1.parse::<usize>()?;
-				let nbeats = nbeats.parse::<usize>()?;
-				if ndiv0 == 0 || ndiv1 == 0 {
-					return Err(LayoutParseError);
+				let ndiv0 = parse_field::<usize>("# divisions (a)", &ndiv0)?;
+				let ndiv1 = parse_field::<usize>("# divisions (b)", &ndiv1)?;
+				let nbeats = parse_field::<usize>("# beats", &nbeats)?;
+				if ndiv0 == 0 {
+					return Err(LayoutParseError::field("# divisions (a)", "must be at least 1"));
+				}
+				if ndiv1 == 0 {
+					return Err(LayoutParseError::field("# divisions (b)", "must be at least 1"));
 				}
 				let mut out: Vec<f32> = (0..ndiv0)
 					.map(|k| k as f32 / ndiv0 as f32)
@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+
+use super::{parse_field, LayoutParseError, Pattern};
+
+// the contents of a Scala .scl file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scl {
+	pub description: String,
+	pub degrees: Vec<f32>, // ratios to the unison, the last one is the period
+}
+
+impl Scl {
+	pub fn parse(text: &str) -> Result<Scl, LayoutParseError> {
+		let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+		let description = lines
+			.next()
+			.ok_or_else(|| scl_error("missing description"))?
+			.trim()
+			.to_string();
+		let count = parse_field::<usize>(".scl file", lines.next().ok_or_else(|| scl_error("missing note count"))?)?;
+		let degrees = lines
+			.take(count)
+			.map(|line| parse_pitch(line))
+			.collect::<Result<Vec<f32>, _>>()?;
+		if count == 0 {
+			return Err(scl_error("the scale has no notes"));
+		}
+		if degrees.len() != count {
+			return Err(scl_error(format!("expected {} notes, found {}", count, degrees.len())));
+		}
+		Ok(Scl { description, degrees })
+	}
+
+	pub fn period(&self) -> f32 {
+		self.degrees[self.degrees.len() - 1]
+	}
+}
+
+impl std::fmt::Display for Scl {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		writeln!(
+			f,
+			"! exported from harmoxen\n!\n{}\n{}\n!",
+			self.description,
+			self.degrees.len()
+		)?;
+		for ratio in &self.degrees {
+			writeln!(f, "{:.6}", 1200.0 * ratio.log2())?;
+		}
+		Ok(())
+	}
+}
+
+// the scale and keyboard mapping of a pattern, None if its frequencies are free
+pub fn export(pattern: &Pattern, description: &str) -> Option<(Scl, Kbm)> {
+	let freq = pattern.freq.as_ref()?;
+	let degrees = freq.values[1..].iter().map(|x| x / freq.values[0]).collect::<Vec<f32>>();
+	let mut kbm = pattern.keyboard.clone().unwrap_or_else(|| Kbm {
+		size: 0,
+		first: 0,
+		last: 127,
+		middle: 69,
+		reference: 69,
+		freq: freq.base,
+		octave: degrees.len(),
+		mapping: vec![],
+	});
+	// the base may have moved since the mapping was read, by anchoring the marker
+	if let Some(degree) = kbm.degree(kbm.reference) {
+		kbm.freq = freq.base * freq.degree_ratio(degree);
+	}
+	let scl = Scl {
+		description: description.to_string(),
+		degrees,
+	};
+	Some((scl, kbm))
+}
+
+// a pitch line is either cents if it has a dot, or a ratio, anything after it is a comment
+fn parse_pitch(line: &str) -> Result<f32, LayoutParseError> {
+	let value = line.split_whitespace().next().ok_or_else(|| scl_error("empty pitch line"))?;
+	let ratio = if value.contains('.') {
+		2f32.powf(parse_field::<f32>(".scl file", value)? / 1200.0)
+	} else {
+		let mut parts = value.splitn(2, '/');
+		let num = parse_field::<u64>(".scl file", parts.next().unwrap())?;
+		let den = parts.next().map_or(Ok(1), |x| parse_field::<u64>(".scl file", x))?;
+		num as f32 / den as f32
+	};
+	if ratio > 0.0 && ratio.is_finite() {
+		Ok(ratio)
+	} else {
+		Err(scl_error(format!("invalid pitch {}", value)))
+	}
+}
+
+fn scl_error(reason: impl ToString) -> LayoutParseError {
+	LayoutParseError::field(".scl file", reason)
+}
+
+// the contents of a Scala .kbm file, mapping midi keys to degrees of a scale
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Kbm {
+	pub size: usize, // number of keys after which the mapping repeats, 0 maps every key to the next degree
+	pub first: u8,   // keys outside of first..=last are not mapped
+	pub last: u8,
+	pub middle: u8,    // key mapped to the first degree
+	pub reference: u8, // key tuned to `freq`
+	pub freq: f32,
+	pub octave: usize, // degree the mapping moves by when it repeats
+	pub mapping: Vec<Option<usize>>,
+}
+
+impl Kbm {
+	pub fn parse(text: &str) -> Result<Kbm, LayoutParseError> {
+		let mut lines = text
+			.lines()
+			.filter(|line| !line.starts_with('!'))
+			.map(|line| line.split_whitespace().next().unwrap_or(""));
+		let mut next = |name: &str| lines.next().ok_or_else(|| kbm_error(format!("missing {}", name)));
+		let size = parse_field::<usize>(".kbm file", next("map size")?)?;
+		let first = parse_key(next("first key")?)?;
+		let last = parse_key(next("last key")?)?;
+		let middle = parse_key(next("middle key")?)?;
+		let reference = parse_key(next("reference key")?)?;
+		let freq = parse_field::<f32>(".kbm file", next("reference frequency")?)?;
+		let octave = parse_field::<usize>(".kbm file", next("formal octave")?)?;
+		// missing entries leave the remaining keys unmapped
+		let mut mapping = vec![];
+		while mapping.len() < size {
+			match lines.next() {
+				Some("x") => mapping.push(None),
+				Some(degree) => mapping.push(Some(parse_field::<usize>(".kbm file", degree)?)),
+				None => break,
+			}
+		}
+		mapping.resize(size, None);
+		if !(freq > 0.0 && freq.is_finite()) {
+			return Err(kbm_error("the reference frequency must be positive"));
+		}
+		if first > last {
+			return Err(kbm_error("the first key is after the last one"));
+		}
+		Ok(Kbm {
+			size,
+			first,
+			last,
+			middle,
+			reference,
+			freq,
+			octave,
+			mapping,
+		})
+	}
+
+	// degree of the scale played by a key, counted from the middle key
+	pub fn degree(&self, key: u8) -> Option<i32> {
+		if key < self.first || key > self.last {
+			return None;
+		}
+		let offset = key as i32 - self.middle as i32;
+		if self.size == 0 {
+			Some(offset)
+		} else {
+			let octaves = offset.div_euclid(self.size as i32);
+			let entry = self.mapping[offset.rem_euclid(self.size as i32) as usize]?;
+			Some(entry as i32 + octaves * self.octave as i32)
+		}
+	}
+}
+
+impl std::fmt::Display for Kbm {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		writeln!(f, "! Map size:\n{}", self.size)?;
+		writeln!(f, "! First MIDI note number to retune:\n{}", self.first)?;
+		writeln!(f, "! Last MIDI note number to retune:\n{}", self.last)?;
+		writeln!(
+			f,
+			"! Middle note where the first entry of the mapping is mapped to:\n{}",
+			self.middle
+		)?;
+		writeln!(f, "! Reference note for which frequency is given:\n{}", self.reference)?;
+		writeln!(f, "! Frequency to tune the above note to:\n{:.6}", self.freq)?;
+		writeln!(f, "! Scale degree to consider as formal octave:\n{}", self.octave)?;
+		writeln!(f, "! Mapping:")?;
+		for entry in &self.mapping {
+			match entry {
+				Some(degree) => writeln!(f, "{}", degree)?,
+				None => writeln!(f, "x")?,
+			}
+		}
+		Ok(())
+	}
+}
+
+fn parse_key(value: &str) -> Result<u8, LayoutParseError> {
+	match parse_field::<u8>(".kbm file", value)? {
+		key if key < 128 => Ok(key),
+		key => Err(kbm_error(format!("{} is not a midi key", key))),
+	}
+}
+
+fn kbm_error(reason: impl ToString) -> LayoutParseError {
+	LayoutParseError::field(".kbm file", reason)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::layout::FreqPattern;
+
+	#[test]
+	fn parses_scl_files() {
+		let text = "! meantone.scl\n!\n\n 3\n! degrees\n696.578 fifth\n5/4 major third\n2\n";
+		let scl = Scl::parse(text).unwrap();
+		assert_eq!(scl.description, "");
+		assert_eq!(scl.degrees.len(), 3);
+		assert!((scl.degrees[0] - 2f32.powf(696.578 / 1200.0)).abs() < 1e-5);
+		assert_eq!(scl.degrees[1], 1.25);
+		assert_eq!(scl.period(), 2.0);
+	}
+
+	#[test]
+	fn reads_back_exported_scl() {
+		let scl = Scl {
+			description: "pelog".to_string(),
+			degrees: vec![1.125, 1.5, 2.0],
+		};
+		let parsed = Scl::parse(&scl.to_string()).unwrap();
+		assert_eq!(parsed.description, "pelog");
+		for (a, b) in parsed.degrees.iter().zip(&scl.degrees) {
+			assert!((a - b).abs() < 1e-5);
+		}
+	}
+
+	#[test]
+	fn rejects_malformed_scl() {
+		assert!(Scl::parse("! only comments\n").is_err());
+		assert!(Scl::parse("no count\n").is_err());
+		assert!(Scl::parse("empty\n0\n").is_err());
+		assert!(Scl::parse("short\n3\n3/2\n2/1\n").is_err());
+		assert!(Scl::parse("negative\n1\n-3/2\n").is_err());
+		assert!(Scl::parse("zero\n1\n0/1\n").is_err());
+	}
+
+	#[test]
+	fn exports_the_current_base() {
+		let kbm = Kbm::parse("0\n0\n127\n60\n69\n440\n0\n").unwrap();
+		let mut pattern = Pattern {
+			time: None,
+			// the reference key is 9 degrees above the middle one
+			freq: Some(FreqPattern::new(440.0 / 24.0, vec![1.0, 1.5, 2.0])),
+			keyboard: Some(kbm),
+		};
+		let (scl, kbm) = export(&pattern, "fifths").unwrap();
+		assert_eq!(scl.degrees, vec![1.5, 2.0]);
+		assert!((kbm.freq - 440.0).abs() < 1e-3);
+		pattern.freq.as_mut().unwrap().base = 220.0;
+		assert_eq!(export(&pattern, "fifths").unwrap().1.freq, 220.0 * 24.0);
+		pattern.keyboard = None;
+		assert_eq!(export(&pattern, "fifths").unwrap().1.freq, 220.0);
+	}
+
+	#[test]
+	fn parses_kbm_files() {
+		let text = "! white keys\n7\n0\n127\n60\n69\n440.0\n12\n! mapping\n0\nx\n2 d\n4\n5\n";
+		let kbm = Kbm::parse(text).unwrap();
+		assert_eq!(kbm.size, 7);
+		assert_eq!((kbm.first, kbm.last, kbm.middle, kbm.reference), (0, 127, 60, 69));
+		assert_eq!(kbm.freq, 440.0);
+		assert_eq!(kbm.mapping, vec![Some(0), None, Some(2), Some(4), Some(5), None, None]);
+		assert_eq!(kbm.degree(60), Some(0));
+		assert_eq!(kbm.degree(61), None);
+		assert_eq!(kbm.degree(62), Some(2));
+		assert_eq!(kbm.degree(67), Some(12));
+		assert_eq!(kbm.degree(56), Some(-8));
+		assert_eq!(Kbm::parse(&kbm.to_string()).unwrap(), kbm);
+	}
+
+	#[test]
+	fn maps_every_key_without_a_mapping() {
+		let kbm = Kbm::parse("0\n21\n108\n60\n60\n261.625\n0\n").unwrap();
+		assert_eq!(kbm.degree(60), Some(0));
+		assert_eq!(kbm.degree(21), Some(-39));
+		assert_eq!(kbm.degree(20), None);
+		assert_eq!(kbm.degree(109), None);
+	}
+
+	#[test]
+	fn rejects_malformed_kbm() {
+		assert!(Kbm::parse("0\n0\n127\n60\n69\n").is_err());
+		assert!(Kbm::parse("0\n0\n128\n60\n69\n440\n0\n").is_err());
+		assert!(Kbm::parse("0\n100\n20\n60\n69\n440\n0\n").is_err());
+		assert!(Kbm::parse("0\n0\n127\n60\n69\n0\n0\n").is_err());
+		assert!(Kbm::parse("2\n0\n127\n60\n69\n440\n1\n0\ny\n").is_err());
+	}
+}
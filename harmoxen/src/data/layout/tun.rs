@@ -0,0 +1,34 @@
+use super::Pattern;
+use std::fmt::Write;
+
+// frequency of midi key 0 in 12-tone equal temperament, which .tun tables are relative to
+const BASE_FREQ: f64 = 8.175798915643707;
+
+// an AnaMark .tun file tuning all 128 midi keys, None if the frequencies of the pattern are free
+pub fn export(pattern: &Pattern, name: &str) -> Option<String> {
+	pattern.freq.as_ref()?;
+	// keys left unmapped by the keyboard mapping keep their usual tuning
+	let cents = (0..128u8)
+		.map(|key| match pattern.key_freq(key) {
+			Some(freq) => 1200.0 * (freq as f64 / BASE_FREQ).log2(),
+			None => 100.0 * key as f64,
+		})
+		.collect::<Vec<f64>>();
+
+	let mut out = String::new();
+	writeln!(out, "; exported from harmoxen").ok()?;
+	writeln!(out, "[Scale Begin]\nFormat= \"AnaMark-TUN\"\nFormatVersion= 200").ok()?;
+	writeln!(out, "FormatSpecs= \"http://www.mark-henning.de/eternity/tuningspecs.html\"\n").ok()?;
+	writeln!(out, "[Info]\nName= \"{}\"\n", name).ok()?;
+	// older readers only know the rounded table
+	writeln!(out, "[Tuning]").ok()?;
+	for (key, cents) in cents.iter().enumerate() {
+		writeln!(out, "note {}={}", key, cents.round() as i64).ok()?;
+	}
+	writeln!(out, "\n[Exact Tuning]\nBaseFreq= {}", BASE_FREQ).ok()?;
+	for (key, cents) in cents.iter().enumerate() {
+		writeln!(out, "note {}= {:.6}", key, cents).ok()?;
+	}
+	writeln!(out, "\n[Scale End]").ok()?;
+	Some(out)
+}
@@ -27,3 +27,9 @@ pub mod layout;
 pub use layout::Layout;
 pub mod sheet;
 pub use sheet::Sheet;
+pub mod track;
+pub use track::Track;
+pub mod playback;
+pub use playback::Playback;
+pub mod tempo;
+pub use tempo::TempoMap;
@@ -0,0 +1,88 @@
+use super::{Range, Sheet};
+use serde::{Deserialize, Serialize};
+
+// where playback wraps or stops, shared by the editor and the backends so that they agree
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Playback {
+	pub looping: bool,         // if false, play to the end of the sheet and stop
+	pub region: Option<Range>, // loop start and end, the whole sheet if unset
+}
+
+impl Default for Playback {
+	fn default() -> Playback {
+		Playback {
+			looping: true,
+			region: None,
+		}
+	}
+}
+
+impl Playback {
+	// next time at which the cursor leaves its path, and where it continues from, if it doesn't stop there
+	pub fn boundary(&self, cursor: f32, sheet: &Sheet) -> (f32, Option<f32>) {
+		let end = sheet.get_size().ceil().max(1.0);
+		match self.region {
+			Some(region) if self.looping && cursor < region.1 => (region.1, Some(region.0)),
+			Some(region) if self.looping => (end, Some(region.0)),
+			_ if self.looping => (end, Some(0.0)),
+			_ => (end, None),
+		}
+	}
+
+	// position of the cursor after some time, None if playback stopped
+	pub fn advance(&self, cursor: f32, length: f32, sheet: &Sheet) -> Option<f32> {
+		let (boundary, jump) = self.boundary(cursor, sheet);
+		if cursor + length < boundary {
+			Some(cursor + length)
+		} else {
+			jump.map(|start| start + (cursor + length - boundary.max(cursor)))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::{sheet::Note, Point};
+
+	#[test]
+	fn loops_over_the_sheet() {
+		let mut sheet = Sheet::default();
+		sheet.add_note(Note::new(Point::new(1.0, 8.0), 2.0)); // ends at beat 3
+		let playback = Playback::default();
+		assert_eq!(playback.advance(1.0, 0.5, &sheet), Some(1.5));
+		assert_eq!(playback.advance(2.5, 1.0, &sheet), Some(0.5));
+		// from past the end, the cursor comes back by the time it played
+		assert_eq!(playback.advance(5.0, 0.25, &sheet), Some(0.25));
+	}
+
+	#[test]
+	fn loops_over_the_region() {
+		let mut sheet = Sheet::default();
+		sheet.add_note(Note::new(Point::new(1.0, 8.0), 2.0)); // ends at beat 3
+		let playback = Playback {
+			looping: true,
+			region: Some(Range(1.0, 2.0)),
+		};
+		assert_eq!(playback.advance(1.5, 0.25, &sheet), Some(1.75));
+		assert_eq!(playback.advance(1.5, 1.0, &sheet), Some(1.5));
+		// after the region, the cursor plays to the end of the sheet before jumping back
+		assert_eq!(playback.advance(2.5, 0.25, &sheet), Some(2.75));
+		assert_eq!(playback.advance(2.5, 1.0, &sheet), Some(1.5));
+	}
+
+	#[test]
+	fn stops_at_the_end() {
+		let mut sheet = Sheet::default();
+		sheet.add_note(Note::new(Point::new(1.0, 8.0), 2.0)); // ends at beat 3
+		let playback = Playback {
+			looping: false,
+			region: Some(Range(1.0, 2.0)),
+		};
+		assert_eq!(playback.advance(1.5, 1.0, &sheet), Some(2.5));
+		assert_eq!(playback.advance(2.5, 1.0, &sheet), None);
+		// an empty sheet still plays for a beat
+		assert_eq!(playback.advance(0.5, 0.25, &Sheet::default()), Some(0.75));
+		assert_eq!(playback.advance(0.5, 0.5, &Sheet::default()), None);
+	}
+}
@@ -1,6 +1,7 @@
+use serde::{Deserialize, Serialize};
 use std::ops;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
 pub struct Range(pub f32, pub f32);
 
 impl Default for Range {
@@ -0,0 +1,328 @@
+use super::{
+	note::{Note, Pitch},
+	Index, Interval, Sheet,
+};
+use crate::data::Range;
+use serde::{Deserialize, Serialize};
+
+// a named group of notes, whose relative pitches point to earlier notes of the clip
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Clip {
+	pub name: String,
+	pub notes: Vec<Note<usize>>,
+	// time span of the notes, relative to the offset of the instances
+	pub span: Range,
+}
+
+// a copy of a clip placed on the sheet, its notes are regenerated whenever the clip changes
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Instance {
+	pub clip: usize,
+	pub offset: f32,
+	pub transpose: Interval,
+	pub notes: Vec<Index>,
+}
+
+impl Instance {
+	pub fn span(&self, clips: &[Clip]) -> Range {
+		let span = clips[self.clip].span;
+		Range(self.offset + span.0, self.offset + span.1)
+	}
+}
+
+impl Sheet {
+	// turns notes into a clip, with a first instance made of these notes
+	pub fn make_clip(&mut self, name: String, notes: &[Index]) -> usize {
+		for instance in &mut self.instances {
+			instance.notes.retain(|idx| !notes.contains(idx));
+		}
+		let offset = notes.iter().fold(f32::INFINITY, |acc, &idx| acc.min(self.notes[idx].start));
+		self.instances.push(Instance {
+			clip: self.clips.len(),
+			offset,
+			transpose: Interval::UNISON,
+			notes: notes.to_vec(),
+		});
+		self.clips.push(Clip {
+			name,
+			notes: vec![],
+			span: Range(0.0, 0.0),
+		});
+		self.update_clip(self.instances.len() - 1);
+		self.clips.len() - 1
+	}
+
+	pub fn add_instance(&mut self, clip: usize, offset: f32, transpose: Interval) -> usize {
+		self.instances.push(Instance {
+			clip,
+			offset,
+			transpose,
+			notes: vec![],
+		});
+		let instance = self.instances.len() - 1;
+		self.materialize(instance, &[]);
+		instance
+	}
+
+	pub fn get_instance_of(&self, idx: Index) -> Option<usize> {
+		self.instances.iter().position(|instance| instance.notes.contains(&idx))
+	}
+
+	pub fn get_instance_at(&self, time: f32) -> Option<usize> {
+		self.instances
+			.iter()
+			.rposition(|instance| instance.span(&self.clips).contains(time))
+	}
+
+	// a note added over an instance becomes part of its clip, the instance is to be synced afterwards
+	pub fn add_to_instance(&mut self, idx: Index) -> Option<usize> {
+		if self.get_instance_of(idx).is_some() {
+			return None;
+		}
+		let instance = self.get_instance_at(self.notes.get(idx)?.start)?;
+		self.instances[instance].notes.push(idx);
+		Some(instance)
+	}
+
+	// propagates the changes made to a note to every instance of its clip
+	pub fn sync_note(&mut self, idx: Index) {
+		if let Some(instance) = self.get_instance_of(idx) {
+			self.sync_instance(instance);
+		}
+	}
+
+	pub fn sync_instance(&mut self, instance: usize) {
+		let origin = self.update_clip(instance);
+		let clip = self.instances[instance].clip;
+		for i in 0..self.instances.len() {
+			if i != instance && self.instances[i].clip == clip {
+				self.materialize(i, &origin);
+			}
+		}
+	}
+
+	pub fn move_instance(&mut self, instance: usize, offset: f32) {
+		self.instances[instance].offset = offset;
+		let origin: Vec<_> = (0..self.instances[instance].notes.len()).map(Some).collect();
+		self.materialize(instance, &origin);
+	}
+
+	pub fn transpose_instance(&mut self, instance: usize, interval: Interval) {
+		let transpose = &mut self.instances[instance].transpose;
+		*transpose = (*transpose * interval).simplify();
+		let origin: Vec<_> = (0..self.instances[instance].notes.len()).map(Some).collect();
+		self.materialize(instance, &origin);
+	}
+
+	// gives an instance its own copy of the clip
+	pub fn make_unique(&mut self, instance: usize) {
+		let mut clip = self.clips[self.instances[instance].clip].clone();
+		clip.name = format!("{} (unique)", clip.name);
+		self.clips.push(clip);
+		self.instances[instance].clip = self.clips.len() - 1;
+	}
+
+	pub fn remove_instance(&mut self, instance: usize) {
+		let instance = self.instances.remove(instance);
+		for idx in instance.notes {
+			if self.notes.contains(idx) {
+				self.remove_note(idx);
+			}
+		}
+	}
+
+	// reads the notes of an instance back into its clip
+	// returns where each note of the clip was in it before, if it was already there
+	fn update_clip(&mut self, instance: usize) -> Vec<Option<usize>> {
+		let Instance {
+			clip,
+			offset,
+			transpose,
+			ref notes,
+		} = self.instances[instance];
+		let mut pending: Vec<Index> = notes.iter().copied().filter(|&idx| self.notes.contains(idx)).collect();
+		// roots must come before the notes relative to them
+		let mut sorted = Vec::with_capacity(pending.len());
+		while !pending.is_empty() {
+			let (ready, rest): (Vec<Index>, Vec<Index>) = pending.iter().partition(|&&idx| match self.notes[idx].pitch {
+				Pitch::Relative(root, _) => !pending.contains(&root),
+				Pitch::Absolute(_) | Pitch::Degree { .. } => true,
+			});
+			if ready.is_empty() {
+				break; // unreachable in a validated sheet
+			}
+			sorted.extend(ready);
+			pending = rest;
+		}
+
+		let clip_notes: Vec<Note<usize>> = sorted
+			.iter()
+			.map(|&idx| {
+				let note = &self.notes[idx];
+				let pitch = match note.pitch {
+					Pitch::Relative(root, interval) => match sorted.iter().position(|&i| i == root) {
+						Some(i) => Pitch::Relative(i, interval),
+						None => Pitch::Absolute(self.get_freq(note.pitch, note.start) * transpose.inverse()),
+					},
+					Pitch::Absolute(freq) => Pitch::Absolute(freq * transpose.inverse()),
+					Pitch::Degree { step, octave } => self.transpose_degree(note.start, step, octave, transpose.inverse()),
+				};
+				let mut clip_note = note.with_pitch(pitch);
+				clip_note.start -= offset;
+				clip_note
+			})
+			.collect();
+		let span = clip_notes.iter().fold(Range(0.0, 0.0), |acc, note| {
+			Range(acc.0.min(note.start), acc.1.max(note.end()))
+		});
+		// the notes of the instance were in the order of the clip, followed by the ones added since
+		let old_len = self.clips[clip].notes.len();
+		let origin = sorted
+			.iter()
+			.map(|idx| notes.iter().position(|i| i == idx).filter(|&pos| pos < old_len))
+			.collect();
+		self.clips[clip].notes = clip_notes;
+		self.clips[clip].span = span;
+		self.instances[instance].notes = sorted;
+		origin
+	}
+
+	// makes the notes of an instance copies of its clip
+	// `origin` tells where each note of the clip was in it before, so that the matching notes are updated in place
+	// and the notes relative to them or the markers anchored to them stay attached
+	fn materialize(&mut self, instance: usize, origin: &[Option<usize>]) {
+		let old = std::mem::take(&mut self.instances[instance].notes);
+		let Instance {
+			clip, offset, transpose, ..
+		} = self.instances[instance];
+		let mut reused = vec![false; old.len()];
+		let mut indices: Vec<Index> = vec![];
+		for i in 0..self.clips[clip].notes.len() {
+			let clip_note = &self.clips[clip].notes[i];
+			let pitch = match clip_note.pitch {
+				Pitch::Relative(root, interval) => Pitch::Relative(indices[root], interval),
+				Pitch::Absolute(freq) => Pitch::Absolute(freq * transpose),
+				Pitch::Degree { step, octave } => self.transpose_degree(clip_note.start + offset, step, octave, transpose),
+			};
+			let mut note = clip_note.with_pitch(pitch);
+			note.start += offset;
+			let prev = origin
+				.get(i)
+				.copied()
+				.flatten()
+				.filter(|&pos| pos < old.len() && self.notes.contains(old[pos]));
+			match prev {
+				Some(pos) => {
+					reused[pos] = true;
+					self.replace_note(old[pos], note);
+					indices.push(old[pos]);
+				}
+				None => indices.push(self.add_note(note)),
+			}
+		}
+		for (pos, idx) in old.into_iter().enumerate() {
+			if !reused[pos] && self.notes.contains(idx) {
+				self.remove_note(idx);
+			}
+		}
+		self.instances[instance].notes = indices;
+	}
+
+	// a degree moved by the transposition of an instance, at the place of the note
+	// degrees follow the layout wherever the instance is, and become absolute if the interval isn't on the scale
+	fn transpose_degree<I>(&self, start: f32, step: i32, octave: i32, transpose: Interval) -> Pitch<I> {
+		if transpose.to_ratio() == Some((1, 1)) {
+			return Pitch::Degree { step, octave };
+		}
+		let freq = self.layout().degree_freq(start, step, octave) * transpose;
+		match self.layout().degree_on(start, freq) {
+			Some(Pitch::Degree { step, octave }) => Pitch::Degree { step, octave },
+			_ => Pitch::Absolute(freq),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::Point;
+
+	#[test]
+	fn instances_follow_their_clip() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note::new(Point::new(0.0, 8.0), 1.0));
+		let b = sheet.add_note(Note {
+			pitch: Pitch::Relative(a, Interval::Ratio(5, 4)),
+			..Note::new(Point::new(1.0, 0.0), 1.0)
+		});
+		let clip = sheet.make_clip("riff".to_string(), &[b, a]);
+		// roots come first in the clip
+		assert_eq!(sheet.instances[0].notes, vec![a, b]);
+		assert_eq!(sheet.clips[clip].span, Range(0.0, 2.0));
+
+		let copy = sheet.add_instance(clip, 4.0, Interval::Ratio(3, 2));
+		let notes = sheet.instances[copy].notes.clone();
+		let freq = |sheet: &Sheet, idx: Index| sheet.get_freq(sheet.notes[idx].pitch, sheet.notes[idx].start);
+		assert_eq!(sheet.notes[notes[0]].start, 4.0);
+		assert_eq!(freq(&sheet, notes[0]), 384.0);
+		assert_eq!(sheet.notes[notes[1]].pitch, Pitch::Relative(notes[0], Interval::Ratio(5, 4)));
+
+		// editing the first instance updates the copy in place
+		sheet.set_pitch(b, Pitch::Relative(a, Interval::Ratio(6, 5))).unwrap();
+		sheet.sync_note(b);
+		assert_eq!(sheet.instances[copy].notes, notes);
+		assert_eq!(sheet.notes[notes[1]].pitch, Pitch::Relative(notes[0], Interval::Ratio(6, 5)));
+
+		// a note added over the copy joins the clip, untransposed in the first instance
+		let c = sheet.add_note(Note::new(Point::new(4.5, 9.0), 0.5));
+		assert_eq!(sheet.add_to_instance(c), Some(copy));
+		sheet.sync_instance(copy);
+		assert_eq!(sheet.instances[0].notes.len(), 3);
+		let added = *sheet.instances[0].notes.iter().find(|&&idx| idx != a && idx != b).unwrap();
+		assert_eq!(sheet.notes[added].start, 0.5);
+		assert!((freq(&sheet, added) - 512.0 / 1.5).abs() < 1e-3);
+
+		// and a note removed from it is gone from the first instance
+		sheet.remove_note(c);
+		sheet.sync_instance(copy);
+		assert_eq!(sheet.instances[0].notes, vec![a, b]);
+		assert!(!sheet.notes.contains(added));
+
+		sheet.transpose_instance(copy, Interval::Ratio(4, 3));
+		assert_eq!(sheet.instances[copy].transpose, Interval::Ratio(2, 1));
+		assert_eq!(freq(&sheet, notes[0]), 512.0);
+	}
+
+	#[test]
+	fn instances_transpose_degrees() {
+		// the default layout is 12-EDO from A4
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note {
+			pitch: Pitch::Degree { step: 0, octave: 0 },
+			..Note::new(Point::new(0.0, 0.0), 1.0)
+		});
+		let clip = sheet.make_clip("a".to_string(), &[a]);
+		// a step of the scale keeps the copy on a degree
+		let up = sheet.add_instance(clip, 2.0, Interval::Edo(7, 12, 2));
+		let up_note = sheet.instances[up].notes[0];
+		assert_eq!(sheet.notes[up_note].pitch, Pitch::Degree { step: 7, octave: 0 });
+		let down = sheet.add_instance(clip, 4.0, Interval::Ratio(1, 2));
+		let down_note = sheet.instances[down].notes[0];
+		assert_eq!(sheet.notes[down_note].pitch, Pitch::Degree { step: 0, octave: -1 });
+		// off the scale, the copy plays the transposed frequency
+		let just = sheet.add_instance(clip, 6.0, Interval::Ratio(3, 2));
+		let just_note = sheet.instances[just].notes[0];
+		match sheet.notes[just_note].pitch {
+			Pitch::Absolute(freq) => assert!((freq - 660.0).abs() < 1e-3),
+			pitch => panic!("{:?}", pitch),
+		}
+
+		// an edit of a transposed copy goes back to the clip untransposed
+		let y = sheet.layout().degree_freq(2.0, 9, 0).log2();
+		sheet.move_note(up_note, 2.0, y);
+		assert_eq!(sheet.notes[up_note].pitch, Pitch::Degree { step: 9, octave: 0 });
+		sheet.sync_instance(up);
+		assert_eq!(sheet.notes[a].pitch, Pitch::Degree { step: 2, octave: 0 });
+		assert_eq!(sheet.notes[down_note].pitch, Pitch::Degree { step: 2, octave: -1 });
+	}
+}
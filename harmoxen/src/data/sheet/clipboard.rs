@@ -1,6 +1,6 @@
 use super::{
 	note::{Note, Pitch},
-	Index as SheetIndex, Sheet,
+	Index as SheetIndex, Sheet, SheetError,
 };
 use std::collections::HashSet;
 
@@ -39,57 +39,63 @@ impl Clipboard {
 	}
 
 	fn cut_copy_impl(&mut self, sheet_note: Note<SheetIndex>, selection: &[SheetIndex]) {
-		let note: Note<Index> = Note {
-			start: sheet_note.start,
-			length: sheet_note.length,
-			pitch: match sheet_note.pitch {
-				Pitch::Absolute(freq) => Pitch::Absolute(freq),
-				Pitch::Relative(idx, interval) => Pitch::Relative(
-					if let Some(i) = selection.iter().position(|&i| i == idx) {
-						Index::ClipboardIndex(i)
-					} else {
-						Index::SheetIndex(idx)
-					},
-					interval,
-				),
-			},
-		};
+		let note: Note<Index> = sheet_note.with_pitch(match sheet_note.pitch {
+			Pitch::Absolute(freq) => Pitch::Absolute(freq),
+			Pitch::Degree { step, octave } => Pitch::Degree { step, octave },
+			Pitch::Relative(idx, interval) => Pitch::Relative(
+				if let Some(i) = selection.iter().position(|&i| i == idx) {
+					Index::ClipboardIndex(i)
+				} else {
+					Index::SheetIndex(idx)
+				},
+				interval,
+			),
+		});
 		self.0.push(note);
 	}
 
-	pub fn paste(&self, sheet: &mut Sheet, selection: &mut HashSet<SheetIndex>) {
-		let mut entries: Vec<(usize, Note<Index>)> = (0..self.0.len()).map(|i| (i, self.0[i].clone())).collect();
-		let mut sheet_indices: Vec<Option<SheetIndex>> = vec![None; self.0.len()];
+	pub fn paste(&self, sheet: &mut Sheet, selection: &mut HashSet<SheetIndex>) -> Result<(), SheetError> {
+		// find an insertion order where roots come before the notes relative to them
+		let mut order = Vec::with_capacity(self.0.len());
+		let mut inserted = vec![false; self.0.len()];
+		while order.len() < self.0.len() {
+			let before = order.len();
+			for (i, note) in self.0.iter().enumerate() {
+				if inserted[i] {
+					continue;
+				}
+				let ready = match note.pitch {
+					Pitch::Relative(Index::ClipboardIndex(root), _) => inserted[root],
+					Pitch::Relative(Index::SheetIndex(root), _) if !sheet.notes.contains(root) => {
+						return Err(SheetError::DanglingRoot(root));
+					}
+					Pitch::Relative(Index::SheetIndex(_), _) | Pitch::Absolute(_) | Pitch::Degree { .. } => true,
+				};
+				if ready {
+					inserted[i] = true;
+					order.push(i);
+				}
+			}
+			if order.len() == before {
+				return Err(SheetError::Cycle);
+			}
+		}
 
+		let mut sheet_indices: Vec<Option<SheetIndex>> = vec![None; self.0.len()];
 		selection.clear();
-		while entries.len() > 0 {
-			let entry = entries.pop().unwrap();
-			let (i, note) = entry;
+		for i in order {
+			let note = &self.0[i];
 			let pitch = match note.pitch {
-				Pitch::Relative(idx, interval) => match idx {
-					Index::ClipboardIndex(idx) => {
-						if let Some(sheet_idx) = sheet_indices[idx] {
-							Pitch::<SheetIndex>::Relative(sheet_idx, interval)
-						} else {
-							entries.push(entry);
-							let parent = entries.swap_remove(idx);
-							entries.push(parent);
-							continue;
-						}
-					}
-					Index::SheetIndex(sheet_idx) => Pitch::<SheetIndex>::Relative(sheet_idx, interval),
-				},
-				Pitch::Absolute(freq) => Pitch::<SheetIndex>::Absolute(freq),
-			};
-
-			let sheet_note = SheetNote {
-				start: note.start,
-				length: note.length,
-				pitch,
+				Pitch::Relative(Index::ClipboardIndex(idx), interval) => Pitch::Relative(sheet_indices[idx].unwrap(), interval),
+				Pitch::Relative(Index::SheetIndex(idx), interval) => Pitch::Relative(idx, interval),
+				Pitch::Absolute(freq) => Pitch::Absolute(freq),
+				Pitch::Degree { step, octave } => Pitch::Degree { step, octave },
 			};
+			let sheet_note: SheetNote = note.with_pitch(pitch);
 			let index = sheet.add_note(sheet_note);
 			sheet_indices[i] = Some(index);
 			selection.insert(index);
 		}
+		Ok(())
 	}
 }
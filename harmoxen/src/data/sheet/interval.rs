@@ -3,20 +3,316 @@ use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::ops::Mul;
 
+pub const PRIMES: [usize; 9] = [2, 3, 5, 7, 11, 13, 17, 19, 23];
+
+// ratios that need more steps than this are too complex to be useful
+const APPROXIMATION_STEPS: usize = 4096;
+
 #[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
 pub enum Interval {
 	Ratio(usize, usize),
 	Float(f32),
+	Cents(f32),
+	Edo(isize, usize, usize), // steps, divisions, equave
+	Monzo(Monzo),
+}
+
+// exponents of the first primes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Monzo(pub [i32; PRIMES.len()]);
+
+impl Interval {
+	pub const UNISON: Interval = Interval::Ratio(1, 1);
+
+	pub fn to_f32(&self) -> f32 {
+		self.to_f64() as f32
+	}
+
+	pub fn to_f64(&self) -> f64 {
+		match *self {
+			Interval::Ratio(num, denom) => num as f64 / denom as f64,
+			Interval::Float(x) => x as f64,
+			Interval::Cents(cents) => 2f64.powf(cents as f64 / 1200.0),
+			Interval::Edo(steps, ndiv, equave) => (equave as f64).powf(steps as f64 / ndiv as f64),
+			Interval::Monzo(monzo) => monzo.to_f64(),
+		}
+	}
+
+	pub fn to_cents(&self) -> f32 {
+		match *self {
+			Interval::Cents(cents) => cents,
+			_ => (1200.0 * self.to_f64().log2()) as f32,
+		}
+	}
+
+	// exact value as a simplified fraction, if there is one that fits
+	pub fn to_ratio(&self) -> Option<(usize, usize)> {
+		match *self {
+			Interval::Ratio(num, denom) if num != 0 && denom != 0 => {
+				let d = gcd(num, denom);
+				Some((num / d, denom / d))
+			}
+			Interval::Monzo(monzo) => monzo.to_ratio(),
+			Interval::Edo(steps, ndiv, equave) if steps % ndiv as isize == 0 => {
+				let power = equave.checked_pow((steps.abs() as usize / ndiv) as u32)?;
+				Some(if steps >= 0 { (power, 1) } else { (1, power) })
+			}
+			_ => None,
+		}
+	}
+
+	// exact value as prime exponents, if it is rational and smooth enough
+	pub fn to_monzo(&self) -> Option<Monzo> {
+		match *self {
+			Interval::Monzo(monzo) => Some(monzo),
+			Interval::Ratio(..) | Interval::Edo(..) => {
+				let (num, denom) = self.to_ratio()?;
+				Some(Monzo::factorize(num)? / Monzo::factorize(denom)?)
+			}
+			_ => None,
+		}
+	}
+
+	pub fn inverse(self) -> Interval {
+		match self {
+			Interval::Ratio(num, denom) => Interval::Ratio(denom, num),
+			Interval::Float(x) => Interval::Float(1.0 / x),
+			Interval::Cents(cents) => Interval::Cents(-cents),
+			Interval::Edo(steps, ndiv, equave) => Interval::Edo(-steps, ndiv, equave),
+			Interval::Monzo(monzo) => Interval::Monzo(Monzo::default() / monzo),
+		}
+	}
+
+	pub fn pow(self, n: i32) -> Interval {
+		if n < 0 {
+			return self.inverse().pow(-n);
+		}
+		match self {
+			Interval::Ratio(num, denom) => match (num.checked_pow(n as u32), denom.checked_pow(n as u32)) {
+				(Some(num), Some(denom)) => Interval::Ratio(num, denom),
+				_ => match self.to_monzo() {
+					Some(monzo) => Interval::Monzo(monzo.pow(n)),
+					None => Interval::Cents(self.to_cents() * n as f32),
+				},
+			},
+			Interval::Float(x) => Interval::Float(x.powi(n)),
+			Interval::Cents(cents) => Interval::Cents(cents * n as f32),
+			Interval::Edo(steps, ndiv, equave) => Interval::Edo(steps * n as isize, ndiv, equave),
+			Interval::Monzo(monzo) => Interval::Monzo(monzo.pow(n)),
+		}
+	}
+
+	// brings the interval within [1, period) by stacking periods
+	// intervals that aren't positive and finite, or periods that don't go up, are left unchanged
+	pub fn reduce(self, period: Interval) -> Interval {
+		let (value, period_value) = (self.to_f64(), period.to_f64());
+		if !(value > 0.0 && value.is_finite() && period_value > 1.0 && period_value.is_finite()) {
+			return self;
+		}
+		let n = (value.ln() / period_value.ln()).floor() as i32;
+		let mut reduced = (self * period.pow(-n)).simplify();
+		// guard against rounding errors at the boundaries
+		if reduced.to_f64() >= period_value * (1.0 - 1e-9) {
+			reduced = (reduced * period.inverse()).simplify();
+		} else if reduced.to_f64() < 1.0 - 1e-9 {
+			reduced = (reduced * period).simplify();
+		}
+		reduced
+	}
+
+	pub fn simplify(self) -> Interval {
+		match self {
+			Interval::Ratio(num, denom) if num != 0 && denom != 0 => {
+				let d = gcd(num, denom);
+				Interval::Ratio(num / d, denom / d)
+			}
+			Interval::Edo(steps, ndiv, equave) => {
+				let d = gcd(steps.abs() as usize, ndiv).max(1);
+				Interval::Edo(steps / d as isize, ndiv / d, equave)
+			}
+			_ => self,
+		}
+	}
+
+	// converts to the given kind of interval, as exactly as possible
+	pub fn to_ratio_interval(self) -> Option<Interval> {
+		self.to_ratio().map(|(num, denom)| Interval::Ratio(num, denom))
+	}
+	pub fn to_monzo_interval(self) -> Option<Interval> {
+		self.to_monzo().map(Interval::Monzo)
+	}
+	pub fn to_cents_interval(self) -> Interval {
+		Interval::Cents(self.to_cents())
+	}
+	pub fn to_float_interval(self) -> Interval {
+		Interval::Float(self.to_f32())
+	}
+	// simplest ratio within a tolerance in cents of the given interval, found by walking the Stern-Brocot tree
+	pub fn approximate(value: f32, tolerance: f32) -> Option<Interval> {
+		let value = value as f64;
+		if !(value > 0.0) || !value.is_finite() {
+			return None;
+		}
+		let spread = 2f64.powf(tolerance.abs() as f64 / 1200.0);
+		let (lo, hi) = (value / spread, value * spread);
+		let (mut a, mut b, mut c, mut d) = (0usize, 1usize, 1usize, 0usize);
+		for _ in 0..APPROXIMATION_STEPS {
+			let (num, denom) = (a + c, b + d);
+			let mediant = num as f64 / denom as f64;
+			if mediant < lo {
+				a = num;
+				b = denom;
+			} else if mediant > hi {
+				c = num;
+				d = denom;
+			} else {
+				return Some(Interval::Ratio(num, denom));
+			}
+		}
+		None
+	}
+
+	// Tenney height of the interval, a measure of its complexity
+	pub fn height(&self) -> Option<f32> {
+		self.to_ratio()
+			.map(|(num, denom)| (num as f32).log2() + (denom as f32).log2())
+	}
+
+	// number of equaves the interval stacks, if it is an exact power of it
+	fn equave_power(&self, equave: usize) -> Option<isize> {
+		let (mut n, sign) = match self.to_ratio()? {
+			(num, 1) => (num, 1),
+			(1, denom) => (denom, -1),
+			_ => return None,
+		};
+		let mut power = 0;
+		while n > 1 {
+			if n % equave != 0 {
+				return None;
+			}
+			n /= equave;
+			power += 1;
+		}
+		Some(sign * power)
+	}
+
+	// closest step of the given equal division
+	pub fn to_edo_interval(self, ndiv: usize, equave: usize) -> Interval {
+		let steps = (self.to_f64().ln() / (equave as f64).ln() * ndiv as f64).round() as isize;
+		Interval::Edo(steps, ndiv, equave).simplify()
+	}
+}
+
+impl Mul<Interval> for Interval {
+	type Output = Interval;
+	fn mul(self, rhs: Interval) -> Interval {
+		use Interval::*;
+		match (self, rhs) {
+			(Float(_), _) | (_, Float(_)) => Float(self.to_f32() * rhs.to_f32()),
+			(Edo(steps0, ndiv0, equave0), Edo(steps1, ndiv1, equave1)) if equave0 == equave1 => {
+				let ndiv = ndiv0 * ndiv1 / gcd(ndiv0, ndiv1);
+				let steps = steps0 * (ndiv / ndiv0) as isize + steps1 * (ndiv / ndiv1) as isize;
+				Edo(steps, ndiv, equave0)
+			}
+			// stacking equaves keeps a step of the division exact
+			(Edo(steps, ndiv, equave), other) | (other, Edo(steps, ndiv, equave)) if other.equave_power(equave).is_some() => {
+				Edo(steps + other.equave_power(equave).unwrap() * ndiv as isize, ndiv, equave)
+			}
+			(Ratio(num0, denom0), Ratio(num1, denom1)) => {
+				// cross-simplify first to keep the terms small
+				let (a, b) = (gcd(num0, denom1).max(1), gcd(num1, denom0).max(1));
+				match ((num0 / a).checked_mul(num1 / b), (denom0 / b).checked_mul(denom1 / a)) {
+					(Some(num), Some(denom)) => Ratio(num, denom).simplify(),
+					_ => match (self.to_monzo(), rhs.to_monzo()) {
+						(Some(m0), Some(m1)) => Monzo(m0 * m1),
+						_ => Cents(self.to_cents() + rhs.to_cents()),
+					},
+				}
+			}
+			_ => match (self.to_monzo(), rhs.to_monzo()) {
+				(Some(m0), Some(m1)) => Monzo(m0 * m1),
+				_ => Cents(self.to_cents() + rhs.to_cents()),
+			},
+		}
+	}
+}
+
+impl Monzo {
+	pub fn to_f64(&self) -> f64 {
+		PRIMES
+			.iter()
+			.zip(self.0.iter())
+			.fold(1.0, |acc, (&p, &e)| acc * (p as f64).powi(e))
+	}
+
+	pub fn to_ratio(&self) -> Option<(usize, usize)> {
+		let (mut num, mut denom) = (1usize, 1usize);
+		for (&p, &e) in PRIMES.iter().zip(self.0.iter()) {
+			let power = p.checked_pow(e.abs() as u32)?;
+			if e > 0 {
+				num = num.checked_mul(power)?;
+			} else {
+				denom = denom.checked_mul(power)?;
+			}
+		}
+		Some((num, denom))
+	}
+
+	// None if n has a prime factor that does not fit in a monzo
+	pub fn factorize(mut n: usize) -> Option<Monzo> {
+		if n == 0 {
+			return None;
+		}
+		let mut monzo = Monzo::default();
+		for (i, &p) in PRIMES.iter().enumerate() {
+			while n % p == 0 {
+				n /= p;
+				monzo.0[i] += 1;
+			}
+		}
+		if n == 1 {
+			Some(monzo)
+		} else {
+			None
+		}
+	}
+
+	pub fn pow(self, n: i32) -> Monzo {
+		let mut out = self;
+		out.0.iter_mut().for_each(|e| *e *= n);
+		out
+	}
+}
+
+impl Mul<Monzo> for Monzo {
+	type Output = Monzo;
+	fn mul(mut self, rhs: Monzo) -> Monzo {
+		self.0.iter_mut().zip(rhs.0.iter()).for_each(|(e, f)| *e += f);
+		self
+	}
+}
+
+impl std::ops::Div<Monzo> for Monzo {
+	type Output = Monzo;
+	fn div(self, rhs: Monzo) -> Monzo {
+		self * rhs.pow(-1)
+	}
 }
 
 impl Mul<Interval> for f32 {
 	type Output = f32;
 	fn mul(self, interval: Interval) -> f32 {
-		match interval {
-			Interval::Ratio(num, denom) => self * (num as f32 / denom as f32),
-			Interval::Float(x) => self * x,
-		}
+		self * interval.to_f32()
+	}
+}
+
+pub(super) fn gcd(mut a: usize, mut b: usize) -> usize {
+	while b != 0 {
+		let t = b;
+		b = a % b;
+		a = t;
 	}
+	a
 }
 
 #[derive(Debug, Display)]
@@ -27,18 +323,71 @@ impl std::str::FromStr for Interval {
 	type Err = IntervalParseError;
 
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		if let Some(cents) = s.strip_suffix('c') {
+			let cents = cents.trim().parse::<f32>().map_err(|_| IntervalParseError)?;
+			if !cents.is_finite() {
+				return Err(IntervalParseError);
+			}
+			return Ok(Interval::Cents(cents));
+		}
+		if s.starts_with('[') {
+			return Ok(Interval::Monzo(s.parse::<Monzo>()?));
+		}
+		if let Some(backslash) = s.find('\\') {
+			// steps\divisions, optionally followed by <equave>
+			let steps = s[..backslash].trim().parse::<isize>().map_err(|_| IntervalParseError)?;
+			let rest = &s[backslash + 1..];
+			let (ndiv, equave) = match rest.find('<') {
+				Some(lt) => {
+					let equave = rest[lt + 1..].strip_suffix('>').ok_or(IntervalParseError)?;
+					(&rest[..lt], equave.trim().parse::<usize>().map_err(|_| IntervalParseError)?)
+				}
+				None => (rest, 2),
+			};
+			let ndiv = ndiv.trim().parse::<usize>().map_err(|_| IntervalParseError)?;
+			if ndiv == 0 || equave < 2 {
+				return Err(IntervalParseError);
+			}
+			return Ok(Interval::Edo(steps, ndiv, equave));
+		}
+		// an interval is a positive ratio, so zero terms are rejected
 		let parts = s.split("/").collect::<Vec<&str>>();
 		match parts.len() {
-			1 => Ok(Interval::Float(parts[0].parse::<f32>().map_err(|_| IntervalParseError)?)),
-			2 => Ok(Interval::Ratio(
-				parts[0].parse::<usize>().map_err(|_| IntervalParseError)?,
-				parts[1].parse::<usize>().map_err(|_| IntervalParseError)?,
-			)),
+			1 => match parts[0].parse::<f32>().map_err(|_| IntervalParseError)? {
+				x if x > 0.0 && x.is_finite() => Ok(Interval::Float(x)),
+				_ => Err(IntervalParseError),
+			},
+			2 => match (parts[0].parse::<usize>(), parts[1].parse::<usize>()) {
+				(Ok(num), Ok(denom)) if num != 0 && denom != 0 => Ok(Interval::Ratio(num, denom)),
+				_ => Err(IntervalParseError),
+			},
 			_ => Err(IntervalParseError),
 		}
 	}
 }
 
+impl std::str::FromStr for Monzo {
+	type Err = IntervalParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let inner = s
+			.trim()
+			.strip_prefix('[')
+			.and_then(|s| s.strip_suffix('>'))
+			.ok_or(IntervalParseError)?;
+		let mut monzo = Monzo::default();
+		let exponents = inner.split(|c: char| c.is_whitespace() || c == ',').filter(|x| !x.is_empty());
+		for (i, exponent) in exponents.enumerate() {
+			if i >= PRIMES.len() {
+				return Err(IntervalParseError);
+			}
+			monzo.0[i] = exponent.parse::<i32>().map_err(|_| IntervalParseError)?;
+		}
+		Ok(monzo)
+	}
+}
+
 use std::fmt;
 
 impl fmt::Display for Interval {
@@ -46,6 +395,164 @@ impl fmt::Display for Interval {
 		match &self {
 			Interval::Ratio(num, denom) => write!(f, "{}/{}", num, denom),
 			Interval::Float(x) => write!(f, "{}", x),
+			Interval::Cents(cents) => write!(f, "{}c", cents),
+			Interval::Edo(steps, ndiv, 2) => write!(f, "{}\\{}", steps, ndiv),
+			Interval::Edo(steps, ndiv, equave) => write!(f, "{}\\{}<{}>", steps, ndiv, equave),
+			Interval::Monzo(monzo) => write!(f, "{}", monzo),
+		}
+	}
+}
+
+impl fmt::Display for Monzo {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let len = self.0.iter().rposition(|&e| e != 0).map_or(1, |i| i + 1);
+		let exponents = self.0[..len].iter().map(|e| e.to_string()).collect::<Vec<String>>();
+		write!(f, "[{}>", exponents.join(" "))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn display_parses_back() {
+		let intervals = [
+			Interval::Ratio(3, 2),
+			Interval::Float(1.25),
+			Interval::Cents(701.955),
+			Interval::Cents(-100.0),
+			Interval::Edo(7, 12, 2),
+			Interval::Edo(-3, 13, 3),
+			Interval::Monzo(Monzo([-1, 1, 0, 0, 0, 0, 0, 0, 0])),
+			Interval::Monzo(Monzo::default()),
+		];
+		for &interval in &intervals {
+			assert_eq!(interval.to_string().parse::<Interval>().unwrap(), interval);
+		}
+	}
+
+	#[test]
+	fn parses_notations() {
+		assert_eq!("5/4".parse::<Interval>().unwrap(), Interval::Ratio(5, 4));
+		assert_eq!(" 386.3 c ".parse::<Interval>().unwrap(), Interval::Cents(386.3));
+		assert_eq!("4\\12".parse::<Interval>().unwrap(), Interval::Edo(4, 12, 2));
+		assert_eq!("1\\13<3>".parse::<Interval>().unwrap(), Interval::Edo(1, 13, 3));
+		assert_eq!(
+			"[-2, 0 1>".parse::<Interval>().unwrap(),
+			Interval::Monzo(Monzo([-2, 0, 1, 0, 0, 0, 0, 0, 0]))
+		);
+		assert_eq!(Interval::Edo(7, 12, 2).to_string(), "7\\12");
+		assert_eq!(Interval::Monzo(Monzo([-1, 1, 0, 0, 0, 0, 0, 0, 0])).to_string(), "[-1 1>");
+	}
+
+	#[test]
+	fn rejects_malformed() {
+		for text in &[
+			"",
+			"3/2/1",
+			"a/b",
+			"1\\0",
+			"1\\12<1>",
+			"1\\12<3",
+			"[1 2",
+			"[1 2 3 4 5 6 7 8 9 10>",
+			"xc",
+			"0/1",
+			"3/0",
+			"0",
+			"-1.5",
+			"inf",
+			"NaNc",
+			"infc",
+		] {
+			assert!(text.parse::<Interval>().is_err(), "{:?} should not parse", text);
+		}
+	}
+
+	fn close(a: Interval, b: f64) -> bool {
+		(a.to_f64() / b).log2().abs() < 1e-6
+	}
+
+	#[test]
+	fn multiplies_exactly_when_possible() {
+		assert_eq!(Interval::Ratio(3, 2) * Interval::Ratio(4, 3), Interval::Ratio(2, 1));
+		assert_eq!(Interval::Ratio(5, 4) * Interval::Ratio(6, 5), Interval::Ratio(3, 2));
+		assert_eq!(Interval::Edo(1, 12, 2) * Interval::Edo(1, 8, 2), Interval::Edo(5, 24, 2));
+		assert_eq!(
+			Interval::Ratio(3, 2) * Interval::Monzo(Monzo([0, 0, 1, 0, 0, 0, 0, 0, 0])),
+			Interval::Monzo(Monzo([-1, 1, 1, 0, 0, 0, 0, 0, 0]))
+		);
+		// terms that overflow fall back to prime exponents
+		let big = Interval::Ratio(3usize.pow(40), 2usize.pow(63));
+		assert_eq!(big * big, Interval::Monzo(Monzo([-126, 80, 0, 0, 0, 0, 0, 0, 0])));
+		// inexact intervals are added in cents
+		assert_eq!(
+			Interval::Cents(100.0) * Interval::Edo(1, 7, 3),
+			Interval::Cents(100.0 + 1200.0 * 3f32.log2() / 7.0)
+		);
+		assert!(close(Interval::Float(1.5) * Interval::Ratio(2, 1), 3.0));
+	}
+
+	#[test]
+	fn inverse_cancels_out() {
+		let intervals = [
+			Interval::Ratio(7, 4),
+			Interval::Edo(5, 17, 2),
+			Interval::Monzo(Monzo([2, -1, 0, 1, 0, 0, 0, 0, 0])),
+			Interval::Cents(315.0),
+			Interval::Float(1.2),
+		];
+		for &interval in &intervals {
+			assert!(close(interval * interval.inverse(), 1.0), "{}", interval);
+		}
+		assert_eq!(Interval::Ratio(7, 4) * Interval::Ratio(7, 4).inverse(), Interval::UNISON);
+		assert_eq!(Interval::Edo(5, 17, 2).inverse(), Interval::Edo(-5, 17, 2));
+	}
+
+	#[test]
+	fn reduces_into_the_period() {
+		let octave = Interval::Ratio(2, 1);
+		assert_eq!(Interval::Ratio(9, 2).reduce(octave), Interval::Ratio(9, 8));
+		assert_eq!(Interval::Ratio(1, 3).reduce(octave), Interval::Ratio(4, 3));
+		assert_eq!(Interval::Ratio(2, 1).reduce(octave), Interval::UNISON);
+		assert_eq!(Interval::Ratio(3, 1).reduce(Interval::Ratio(3, 1)), Interval::UNISON);
+		assert_eq!(Interval::Edo(17, 12, 2).reduce(octave), Interval::Edo(5, 12, 2));
+		let reduced = Interval::Cents(-50.0).reduce(octave);
+		assert!((reduced.to_cents() - 1150.0).abs() < 1e-3);
+		// there is nothing to reduce for intervals that aren't positive and finite
+		assert_eq!(Interval::Ratio(0, 3).reduce(octave), Interval::Ratio(0, 3));
+		assert_eq!(Interval::Ratio(3, 0).reduce(octave), Interval::Ratio(3, 0));
+		assert_eq!(Interval::Float(-2.0).reduce(octave), Interval::Float(-2.0));
+		assert_eq!(Interval::Ratio(3, 2).reduce(Interval::UNISON), Interval::Ratio(3, 2));
+	}
+
+	#[test]
+	fn simplifies_terms() {
+		assert_eq!(Interval::Ratio(6, 4).simplify(), Interval::Ratio(3, 2));
+		assert_eq!(Interval::Edo(4, 12, 2).simplify(), Interval::Edo(1, 3, 2));
+		assert_eq!(Interval::Edo(0, 12, 2).simplify(), Interval::Edo(0, 1, 2));
+		assert_eq!(Interval::Ratio(0, 4).simplify(), Interval::Ratio(0, 4));
+		assert_eq!(Interval::Cents(700.0).simplify(), Interval::Cents(700.0));
+	}
+
+	#[test]
+	fn approximates_by_the_simplest_ratio() {
+		assert_eq!(Interval::approximate(1.5, 1.0), Some(Interval::Ratio(3, 2)));
+		assert_eq!(Interval::approximate(1.0, 0.0), Some(Interval::UNISON));
+		assert_eq!(Interval::approximate(0.001, 0.1), Some(Interval::Ratio(1, 1000)));
+		// the first ratio of the Stern-Brocot tree within the tolerance, even if a closer one exists
+		assert_eq!(Interval::approximate(1.2, 50.0), Some(Interval::Ratio(6, 5)));
+		assert_eq!(Interval::approximate(1.17, 50.0), Some(Interval::Ratio(6, 5)));
+		let fifth = 2f32.powf(7.0 / 12.0);
+		assert_eq!(Interval::approximate(fifth, 5.0), Some(Interval::Ratio(3, 2)));
+		let closer = Interval::approximate(fifth, 0.5).unwrap();
+		assert_ne!(closer, Interval::Ratio(3, 2));
+		assert!((closer.to_cents() - 700.0).abs() <= 0.5);
+		for &value in &[0.0, -1.5, f32::NAN, f32::INFINITY] {
+			assert_eq!(Interval::approximate(value, 1.0), None);
 		}
+		// too complex to be found
+		assert_eq!(Interval::approximate(1e-6, 0.0), None);
 	}
 }
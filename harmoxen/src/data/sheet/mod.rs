@@ -1,9 +1,12 @@
-use crate::data::{icp, Line, Point, Range, Rect};
+use crate::data::{icp, layout::Layout, Line, Point, Range, Rect};
 use crate::util::intersect;
+use derive_more::Display;
 use generational_arena::Arena;
 pub use generational_arena::Index;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 
 mod interval;
 pub use interval::*;
@@ -13,6 +16,21 @@ pub mod note;
 mod clipboard;
 pub use clipboard::Clipboard;
 
+mod clip;
+pub use clip::{Clip, Instance};
+
+mod transform;
+pub use transform::Transform;
+
+mod snap;
+pub use snap::{Limit, Snap};
+
+mod scale;
+
+mod note_index;
+use note_index::NoteIndex;
+
+pub use note::BendPoint;
 pub type Pitch = note::Pitch<Index>;
 pub type Note = note::Note<Index>;
 
@@ -20,37 +38,216 @@ pub type Note = note::Note<Index>;
 pub struct Sheet {
 	pub notes: Arena<Note>,
 	pub indices: Vec<Index>,
+	#[serde(default)]
+	pub clips: Vec<Clip>,
+	#[serde(default)]
+	pub instances: Vec<Instance>,
+	// resolved notes, filled as they get queried and invalidated along relative chains
+	#[serde(skip)]
+	cache: RefCell<HashMap<Index, Resolved>>,
+	// notes that are relative to each note, rebuilt lazily after the graph changes
+	#[serde(skip)]
+	children: RefCell<Option<HashMap<Index, Vec<Index>>>>,
+	// notes by time and pitch, built on the first query and kept up to date afterwards
+	#[serde(skip)]
+	note_index: RefCell<Option<NoteIndex>>,
+	// copy of the editor's layout, which degree pitches are resolved through
+	#[serde(skip)]
+	layout: Layout,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Resolved {
+	freq: f32,
+	gliding: bool,
 }
 
 #[allow(dead_code)]
 impl Sheet {
-	pub fn get_freq(&self, pitch: Pitch) -> f32 {
+	// `start` is the start of the note the pitch belongs to
+	pub fn get_freq(&self, pitch: Pitch, start: f32) -> f32 {
 		match pitch {
 			Pitch::Absolute(freq) => freq,
-			Pitch::Relative(idx, ratio) => self.get_freq(self.notes[idx].pitch) * ratio,
+			Pitch::Relative(idx, ratio) => self.resolve(idx).freq * ratio,
+			Pitch::Degree { step, octave } => self.layout.degree_freq(start, step, octave),
 		}
 	}
 
-	pub fn get_y(&self, pitch: Pitch) -> f32 {
-		self.get_freq(pitch).log2()
+	pub fn layout(&self) -> &Layout {
+		&self.layout
 	}
 
-	pub fn get_notes(&self) -> Vec<(Index, Note)> {
-		self.indices.iter().map(|&idx| (idx, self.notes[idx])).collect()
+	// to be called whenever the layout changes, as it moves the notes on its degrees
+	pub fn set_layout(&mut self, layout: &Layout) {
+		self.layout = layout.clone();
+		self.cache.get_mut().clear();
+		if let Some(note_index) = self.note_index.get_mut() {
+			for (idx, _) in &self.notes {
+				note_index.mark_dirty(idx);
+			}
+		}
 	}
 
-	pub fn get_note(&self, id: Index) -> Option<Note> {
-		self.notes.get(id).map(|note| *note)
+	fn resolve(&self, idx: Index) -> Resolved {
+		if let Some(&resolved) = self.cache.borrow().get(&idx) {
+			return resolved;
+		}
+		let note = &self.notes[idx];
+		let resolved = match note.pitch {
+			Pitch::Absolute(freq) => Resolved {
+				freq,
+				gliding: note.is_bent(),
+			},
+			Pitch::Relative(root, ratio) => {
+				let root = self.resolve(root);
+				Resolved {
+					freq: root.freq * ratio,
+					gliding: root.gliding || note.is_bent(),
+				}
+			}
+			Pitch::Degree { .. } => Resolved {
+				freq: self.get_freq(note.pitch, note.start),
+				gliding: note.is_bent(),
+			},
+		};
+		self.cache.borrow_mut().insert(idx, resolved);
+		resolved
+	}
+
+	// forgets the resolved notes depending on a note, and marks them as moved in the note index
+	fn invalidate(&self, idx: Index) {
+		let mut cache = self.cache.borrow_mut();
+		let mut note_index = self.note_index.borrow_mut();
+		if cache.is_empty() && note_index.is_none() {
+			return;
+		}
+		let mut children = self.children.borrow_mut();
+		let children = children.get_or_insert_with(|| {
+			let mut children = HashMap::<Index, Vec<Index>>::new();
+			for (idx, note) in &self.notes {
+				if let Pitch::Relative(root, _) = note.pitch {
+					children.entry(root).or_default().push(idx);
+				}
+			}
+			children
+		});
+		let mut stack = vec![idx];
+		while let Some(idx) = stack.pop() {
+			cache.remove(&idx);
+			if let Some(note_index) = note_index.as_mut() {
+				note_index.mark_dirty(idx);
+			}
+			if let Some(c) = children.get(&idx) {
+				stack.extend(c);
+			}
+		}
 	}
 
-	pub fn get_note_mut(&mut self, id: Index) -> Option<&mut Note> {
-		self.notes.get_mut(id)
+	// to be called before changing a note in a way that may affect the graph of relative pitches
+	fn graph_changed(&mut self, idx: Index) {
+		self.invalidate(idx);
+		*self.children.get_mut() = None;
 	}
 
-	pub fn get_note_at(&self, pos: Point, note_height: f32) -> Option<Index> {
+	fn query_note_index<F, R>(&self, f: F) -> R
+	where
+		F: FnOnce(&NoteIndex) -> R,
+	{
+		let mut note_index = self.note_index.borrow_mut();
+		let note_index = note_index.get_or_insert_with(|| NoteIndex::build(&self.notes));
+		note_index.refresh(|idx| {
+			let note = self.notes.get(idx)?;
+			Some((Range(note.start.min(note.end()), note.start.max(note.end())), note.y(self)))
+		});
+		f(note_index)
+	}
+
+	// runs a change to the timing of a note, keeping the note index up to date
+	fn retime_note<F>(&mut self, id: Index, f: F)
+	where
+		F: FnOnce(&mut Note),
+	{
+		// degree pitches depend on the time of the note
+		self.invalidate(id);
+		if let Some(note) = self.notes.get_mut(id) {
+			let note_index = self.note_index.get_mut();
+			if let Some(note_index) = note_index {
+				let old = note.clone();
+				f(note);
+				note_index.remove(id, &old);
+				note_index.insert(id, &self.notes[id]);
+			} else {
+				f(note);
+			}
+		}
+	}
+
+	// notes overlapping a time range
+	fn get_notes_during(&self, range: Range) -> Vec<Index> {
+		self.query_note_index(|note_index| note_index.during(range).collect())
+	}
+
+	// notes that may be hit by a shape spanning the given area, as drawn `note_height` tall
+	// notes of the `hidden` tracks can't be picked
+	fn get_visible_notes_in(&self, x: Range, y: Range, note_height: f32, hidden: &HashSet<usize>) -> Vec<Index> {
+		let y = Range(y.0 - note_height / 2.0, y.1 + note_height / 2.0);
+		let mut notes: Vec<Index> = self.query_note_index(|note_index| note_index.in_area(x, y).collect());
+		notes.retain(|&idx| !hidden.contains(&self.notes[idx].track));
+		notes
+	}
+
+	// exact interval between a pitch and the absolute note at the end of its chain
+	pub fn get_root_interval(&self, pitch: Pitch) -> Option<(Index, Interval)> {
+		match pitch {
+			Pitch::Absolute(_) | Pitch::Degree { .. } => None,
+			Pitch::Relative(idx, interval) => Some(match self.get_root_interval(self.notes[idx].pitch) {
+				Some((root, acc)) => (root, acc * interval),
+				None => (idx, interval),
+			}),
+		}
+	}
+
+	pub fn get_y(&self, pitch: Pitch, start: f32) -> f32 {
+		self.get_freq(pitch, start).log2()
+	}
+
+	// frequency of the pitch of a note at a given time, following the bends of its roots
+	pub fn get_freq_at(&self, note: &Note, time: f32) -> f32 {
+		match note.pitch {
+			Pitch::Absolute(_) | Pitch::Degree { .. } => self.get_freq(note.pitch, note.start),
+			Pitch::Relative(idx, ratio) => match self.resolve(idx) {
+				Resolved { freq, gliding: false } => freq * ratio,
+				_ => self.get_note_freq_at(&self.notes[idx], time) * ratio,
+			},
+		}
+	}
+
+	// frequency actually sounding for a note at a given time
+	pub fn get_note_freq_at(&self, note: &Note, time: f32) -> f32 {
+		self.get_freq_at(note, time) * note.bend_at(time - note.start)
+	}
+
+	// whether a note or one of its roots has a pitch envelope
+	pub fn is_gliding(&self, note: &Note) -> bool {
+		note.is_bent()
+			|| match note.pitch {
+				Pitch::Absolute(_) | Pitch::Degree { .. } => false,
+				Pitch::Relative(root, _) => self.resolve(root).gliding,
+			}
+	}
+
+	pub fn get_notes(&self) -> Vec<(Index, Note)> {
+		self.indices.iter().map(|&idx| (idx, self.notes[idx].clone())).collect()
+	}
+
+	pub fn get_note(&self, id: Index) -> Option<Note> {
+		self.notes.get(id).cloned()
+	}
+
+	pub fn get_note_at(&self, pos: Point, note_height: f32, hidden: &HashSet<usize>) -> Option<Index> {
 		let mut closest = (None, f32::INFINITY);
-		for &index in &self.indices {
-			let note = self.notes[index];
+		for index in self.get_visible_notes_in(Range(pos.x, pos.x), Range(pos.y, pos.y), note_height, hidden) {
+			let note = &self.notes[index];
 			let dist = (pos.y - note.y(self)).abs();
 			if note.start <= pos.x && pos.x <= note.start + note.length && dist <= note_height / 2.0 && dist <= closest.1 {
 				closest = (Some(index), dist);
@@ -60,9 +257,10 @@ impl Sheet {
 	}
 
 	// get notes from a point in board coordinates
-	pub fn get_notes_at(&self, pos: Point, note_height: f32) -> Vec<Index> {
+	pub fn get_notes_at(&self, pos: Point, note_height: f32, hidden: &HashSet<usize>) -> Vec<Index> {
 		let mut out = vec![];
-		for (index, note) in &self.notes {
+		for index in self.get_visible_notes_in(Range(pos.x, pos.x), Range(pos.y, pos.y), note_height, hidden) {
+			let note = &self.notes[index];
 			if note.start <= pos.x && note.start + note.length >= pos.x && (pos.y - note.y(self)).abs() <= note_height / 2.0 {
 				out.push(index);
 			}
@@ -71,9 +269,11 @@ impl Sheet {
 	}
 
 	// get notes intersecting a rect in board coordinates
-	pub fn get_notes_rect(&self, rect: Rect, note_height: f32) -> Vec<Index> {
+	pub fn get_notes_rect(&self, rect: Rect, note_height: f32, hidden: &HashSet<usize>) -> Vec<Index> {
 		let mut out = vec![];
-		for (index, note) in &self.notes {
+		let area = rect.abs();
+		for index in self.get_visible_notes_in(Range(area.x0, area.x1), Range(area.y0, area.y1), note_height, hidden) {
+			let note = &self.notes[index];
 			let note_y = note.y(self);
 			if intersect::rect_rect(
 				Rect::from_points(
@@ -90,9 +290,12 @@ impl Sheet {
 		out
 	}
 
-	pub fn get_notes_along(&self, line: Line, note_height: f32) -> Vec<Index> {
+	pub fn get_notes_along(&self, line: Line, note_height: f32, hidden: &HashSet<usize>) -> Vec<Index> {
 		let mut notes = Vec::new();
-		for (idx, note) in &self.notes {
+		let x = Range(line.p0.x.min(line.p1.x), line.p0.x.max(line.p1.x));
+		let y = Range(line.p0.y.min(line.p1.y), line.p0.y.max(line.p1.y));
+		for idx in self.get_visible_notes_in(x, y, note_height, hidden) {
+			let note = &self.notes[idx];
 			let note_y = note.y(self);
 			let rect = Rect::from_points(
 				Point::new(note.start, note_y - note_height / 2.0),
@@ -106,14 +309,15 @@ impl Sheet {
 	}
 
 	pub fn get_size(&self) -> f32 {
-		self.notes.iter().fold(0.0, |tmp, (_, note)| tmp.max(note.end()))
+		self.query_note_index(|note_index| note_index.last_end())
+			.map_or(0.0, |end| end.max(0.0))
 	}
 
 	pub fn get_bounds(&self) -> (Range, Range) {
 		let mut x = Range(f32::INFINITY, f32::NEG_INFINITY);
 		let mut y = Range(f32::INFINITY, f32::NEG_INFINITY);
 		for (_, note) in &self.notes {
-			let freq = self.get_freq(note.pitch);
+			let freq = self.get_freq(note.pitch, note.start);
 			x.0 = x.0.min(note.start);
 			x.1 = x.1.max(note.start + note.length);
 			y.0 = y.0.min(freq);
@@ -122,32 +326,125 @@ impl Sheet {
 		(x, y)
 	}
 
+	// checks that a pitch can be given to a note without breaking the graph of relative pitches
+	pub fn check_pitch(&self, idx: Option<Index>, pitch: Pitch) -> Result<(), SheetError> {
+		let mut visited = HashSet::new();
+		let mut pitch = pitch;
+		while let Pitch::Relative(root, _) = pitch {
+			if Some(root) == idx || !visited.insert(root) {
+				return Err(SheetError::Cycle);
+			}
+			pitch = self.notes.get(root).ok_or(SheetError::DanglingRoot(root))?.pitch;
+		}
+		Ok(())
+	}
+
+	pub fn set_pitch(&mut self, idx: Index, pitch: Pitch) -> Result<(), SheetError> {
+		self.check_pitch(Some(idx), pitch)?;
+		if !self.notes.contains(idx) {
+			return Err(SheetError::DeadNote(idx));
+		}
+		self.graph_changed(idx);
+		self.notes[idx].pitch = pitch;
+		Ok(())
+	}
+
+	// checks a whole sheet, e.g. after loading it from a file
+	pub fn validate(&self) -> Result<(), SheetError> {
+		if let Some(&idx) = self.indices.iter().find(|&&idx| !self.notes.contains(idx)) {
+			return Err(SheetError::DeadNote(idx));
+		}
+		for (idx, note) in &self.notes {
+			if !(note.start.is_finite() && note.length.is_finite()) {
+				return Err(SheetError::InvalidTime(idx));
+			}
+			self.check_pitch(Some(idx), note.pitch)?;
+		}
+		for (i, instance) in self.instances.iter().enumerate() {
+			if instance.clip >= self.clips.len() {
+				return Err(SheetError::MissingClip(i));
+			}
+			if let Some(&idx) = instance.notes.iter().find(|&&idx| !self.notes.contains(idx)) {
+				return Err(SheetError::DeadNote(idx));
+			}
+		}
+		// clip notes are materialized in order, so their roots must come first
+		for (i, clip) in self.clips.iter().enumerate() {
+			for (pos, note) in clip.notes.iter().enumerate() {
+				if let note::Pitch::Relative(root, _) = note.pitch {
+					if root >= pos {
+						return Err(SheetError::ClipRoot(i));
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+
 	pub fn add_note(&mut self, note: Note) -> Index {
 		let index = self.notes.insert(note);
 		self.indices.push(index);
+		*self.children.get_mut() = None;
+		if let Some(note_index) = self.note_index.get_mut() {
+			note_index.insert(index, &self.notes[index]);
+		}
 		index
 	}
 
+	// changes a note while keeping its index, so that what refers to it stays attached
+	// the new pitch must not make a cycle, as it isn't checked
+	fn replace_note(&mut self, id: Index, note: Note) {
+		self.graph_changed(id);
+		if let Some(note_index) = self.note_index.get_mut() {
+			note_index.remove(id, &self.notes[id]);
+			note_index.insert(id, &note);
+		}
+		self.notes[id] = note;
+	}
+
+	// notes on a degree stay on one as long as they are moved onto the scale
 	pub fn move_note(&mut self, id: Index, start: f32, y: f32) {
-		if let Some(note) = self.notes.get_mut(id) {
+		let freq = 2f32.powf(y);
+		let degree = match self.notes.get(id).map(|note| note.pitch) {
+			Some(Pitch::Degree { .. }) => self.layout.degree_on(start, freq),
+			_ => None,
+		};
+		self.retime_note(id, |note| {
 			note.start = start;
-			if let Pitch::Absolute(_) = note.pitch {
-				note.pitch = Pitch::Absolute(2f32.powf(y));
+			match note.pitch {
+				Pitch::Absolute(_) | Pitch::Degree { .. } => note.pitch = degree.unwrap_or(Pitch::Absolute(freq)),
+				Pitch::Relative(..) => {}
 			}
-		}
+		});
 	}
 
 	pub fn resize_note_to(&mut self, id: Index, time: f32) {
+		self.retime_note(id, |note| note.length = time - note.start);
+	}
+
+	pub fn set_bend(&mut self, id: Index, bend: Vec<BendPoint>) {
+		self.invalidate(id);
 		if let Some(note) = self.notes.get_mut(id) {
-			note.length = time - note.start;
+			note.bend = bend;
+		}
+	}
+
+	pub fn set_velocity(&mut self, id: Index, velocity: f32) {
+		if let Some(note) = self.notes.get_mut(id) {
+			note.velocity = velocity;
 		}
 	}
 
 	pub fn remove_note(&mut self, index: Index) -> Option<Note> {
 		let removed = [index].iter().cloned().collect();
 		self.disconnect_children(&removed);
+		self.graph_changed(index);
 		self.indices.retain(|&idx| idx != index);
-		self.notes.remove(index)
+		let note = self.notes.remove(index)?;
+		if let Some(note_index) = self.note_index.get_mut() {
+			note_index.remove(index, &note);
+		}
+		Some(note)
 	}
 
 	fn disconnect_children(&mut self, parents: &HashSet<Index>) {
@@ -155,44 +452,235 @@ impl Sheet {
 		for (idx, note) in &self.notes {
 			if let Pitch::Relative(root, _) = note.pitch {
 				if parents.contains(&root) {
-					children.push((idx, self.get_freq(note.pitch)))
+					children.push((idx, self.get_freq(note.pitch, note.start)))
 				}
 			}
 		}
 		for (idx, freq) in children {
+			self.graph_changed(idx);
 			self.notes[idx].pitch = Pitch::Absolute(freq);
 		}
 	}
 
-	pub fn get_events_at_time(&self, time: f32) -> Vec<icp::Event> {
+	fn get_icp_note(&self, index: Index, note: &Note, time: f32) -> icp::Note {
+		icp::Note {
+			id: index.into_raw_parts().0,
+			freq: self.get_note_freq_at(note, time),
+			velocity: note.velocity,
+			pressure: note.pressure,
+			timbre: note.timbre,
+		}
+	}
+
+	// `tracks` tells which tracks should be played, see `track::routed`
+	pub fn get_events_at_time(&self, time: f32, tracks: &[bool]) -> Vec<icp::Event> {
 		let mut events = vec![];
-		for (index, note) in &self.notes {
-			if note.start < time && note.end() > time {
-				let icp_note = icp::Note {
-					id: index.into_raw_parts().0,
-					freq: self.get_freq(note.pitch),
-				};
-				events.push(icp::Event::NotePlay(icp_note));
+		for index in self.get_notes_during(Range(time, time)) {
+			let note = &self.notes[index];
+			if note.start < time && note.end() > time && is_routed(note, tracks) {
+				events.push(icp::Event::NotePlay(self.get_icp_note(index, note, time)));
 			}
 		}
 		events
 	}
 
-	pub fn get_events(&self, range: Range) -> Vec<icp::Event> {
+	// frequency updates for the gliding notes sounding at a given time
+	pub fn get_bend_events(&self, time: f32, tracks: &[bool]) -> Vec<icp::Event> {
 		let mut events = vec![];
-		for (index, note) in &self.notes {
-			let id = index.into_raw_parts().0;
-			if range.contains(note.start) {
-				let icp_note = icp::Note {
-					id,
-					freq: self.get_freq(note.pitch),
-				};
-				events.push(icp::Event::NotePlay(icp_note));
+		for index in self.get_notes_during(Range(time, time)) {
+			let note = &self.notes[index];
+			if note.start < time && note.end() > time && is_routed(note, tracks) && self.is_gliding(note) {
+				let id = index.into_raw_parts().0;
+				events.push(icp::Event::NoteChangeFreq(id, self.get_note_freq_at(note, time)));
 			}
-			if range.contains(note.end()) {
-				events.push(icp::Event::NoteStop(id));
+		}
+		events
+	}
+
+	// notes are always stopped, so that muting a track doesn't leave notes hanging
+	pub fn get_events(&self, range: Range, tracks: &[bool]) -> Vec<icp::Event> {
+		let (starting, ending): (Vec<Index>, Vec<Index>) = self
+			.query_note_index(|note_index| (note_index.starting_in(range).collect(), note_index.ending_in(range).collect()));
+		let mut events = vec![];
+		for index in starting {
+			let note = &self.notes[index];
+			if !is_routed(note, tracks) {
+				continue;
 			}
+			events.push(icp::Event::NotePlay(self.get_icp_note(index, note, note.start)));
+		}
+		for index in ending {
+			events.push(icp::Event::NoteStop(index.into_raw_parts().0));
 		}
 		events
 	}
 }
+
+fn is_routed(note: &Note, tracks: &[bool]) -> bool {
+	tracks.get(note.track).copied().unwrap_or(false)
+}
+
+// Errors
+
+#[derive(Debug, Display, Clone, PartialEq)]
+pub enum SheetError {
+	#[display(fmt = "Note is relative to itself")]
+	Cycle,
+	#[display(fmt = "Note is relative to a note that doesn't exist")]
+	DanglingRoot(Index),
+	#[display(fmt = "Note doesn't exist")]
+	DeadNote(Index),
+	#[display(fmt = "Note has no valid start or length")]
+	InvalidTime(Index),
+	#[display(fmt = "Instance of a clip that doesn't exist")]
+	MissingClip(usize), // instance
+	#[display(fmt = "Clip note is relative to a note that doesn't come before it")]
+	ClipRoot(usize), // clip
+	#[display(fmt = "Project can't be read: {}", _0)]
+	Unreadable(String), // why the file couldn't be read or deserialized
+}
+impl Error for SheetError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rejects_cycles_and_dangling_roots() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note::new(Point::new(0.0, 8.0), 1.0));
+		let b = sheet.add_note(Note {
+			pitch: Pitch::Relative(a, Interval::Ratio(3, 2)),
+			..Note::new(Point::new(1.0, 0.0), 1.0)
+		});
+		let c = sheet.add_note(Note {
+			pitch: Pitch::Relative(b, Interval::Ratio(5, 4)),
+			..Note::new(Point::new(2.0, 0.0), 1.0)
+		});
+		assert_eq!(
+			sheet.set_pitch(a, Pitch::Relative(a, Interval::UNISON)),
+			Err(SheetError::Cycle)
+		);
+		assert_eq!(
+			sheet.set_pitch(a, Pitch::Relative(c, Interval::UNISON)),
+			Err(SheetError::Cycle)
+		);
+		// a rejected pitch leaves the note as it was
+		assert_eq!(sheet.notes[a].pitch, Pitch::Absolute(256.0));
+		assert_eq!(sheet.get_freq(sheet.notes[c].pitch, 2.0), 480.0);
+		assert_eq!(sheet.validate(), Ok(()));
+
+		let d = sheet.add_note(Note::new(Point::new(3.0, 8.0), 1.0));
+		sheet.remove_note(d);
+		assert_eq!(
+			sheet.set_pitch(a, Pitch::Relative(d, Interval::UNISON)),
+			Err(SheetError::DanglingRoot(d))
+		);
+		assert_eq!(sheet.set_pitch(d, Pitch::Absolute(440.0)), Err(SheetError::DeadNote(d)));
+		assert_eq!(sheet.set_pitch(a, Pitch::Absolute(200.0)), Ok(()));
+		assert_eq!(sheet.get_freq(sheet.notes[c].pitch, 2.0), 375.0);
+	}
+
+	#[test]
+	fn validates_loaded_sheets() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note::new(Point::new(0.0, 8.0), 1.0));
+		let b = sheet.add_note(Note {
+			pitch: Pitch::Relative(a, Interval::Ratio(3, 2)),
+			..Note::new(Point::new(1.0, 0.0), 1.0)
+		});
+		// as if read from a file, where nothing was checked
+		sheet.notes[a].pitch = Pitch::Relative(b, Interval::Ratio(2, 3));
+		assert_eq!(sheet.validate(), Err(SheetError::Cycle));
+		sheet.notes[a].pitch = Pitch::Absolute(256.0);
+		sheet.indices.push(sheet.notes.insert(Note::new(Point::new(0.0, 8.0), 1.0)));
+		let dead = sheet.indices[2];
+		sheet.notes.remove(dead);
+		assert_eq!(sheet.validate(), Err(SheetError::DeadNote(dead)));
+		sheet.indices.pop();
+		sheet.notes[b].length = f32::NAN;
+		assert_eq!(sheet.validate(), Err(SheetError::InvalidTime(b)));
+		sheet.notes[b].length = 1.0;
+
+		let clip = sheet.make_clip("clip".to_string(), &[a, b]);
+		assert_eq!(sheet.validate(), Ok(()));
+		sheet.instances[0].clip = 1;
+		assert_eq!(sheet.validate(), Err(SheetError::MissingClip(0)));
+		sheet.instances[0].clip = clip;
+		sheet.instances[0].notes.push(dead);
+		assert_eq!(sheet.validate(), Err(SheetError::DeadNote(dead)));
+		sheet.instances[0].notes.pop();
+		sheet.clips[clip].notes[1].pitch = note::Pitch::Relative(1, Interval::UNISON);
+		assert_eq!(sheet.validate(), Err(SheetError::ClipRoot(clip)));
+	}
+
+	#[test]
+	fn resolved_notes_follow_their_roots() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note::new(Point::new(0.0, 8.0), 1.0));
+		let b = sheet.add_note(Note {
+			pitch: Pitch::Relative(a, Interval::Ratio(3, 2)),
+			..Note::new(Point::new(1.0, 0.0), 1.0)
+		});
+		let c = sheet.add_note(Note {
+			pitch: Pitch::Relative(b, Interval::Ratio(5, 4)),
+			..Note::new(Point::new(2.0, 0.0), 1.0)
+		});
+		let freq = |sheet: &Sheet, idx: Index| sheet.get_freq(sheet.notes[idx].pitch, sheet.notes[idx].start);
+		assert_eq!(freq(&sheet, c), 480.0);
+		sheet.move_note(a, 0.0, 9.0);
+		assert_eq!(freq(&sheet, b), 768.0);
+		assert_eq!(freq(&sheet, c), 960.0);
+		assert_eq!(sheet.set_pitch(b, Pitch::Relative(a, Interval::Ratio(5, 4))), Ok(()));
+		assert_eq!(freq(&sheet, c), 800.0);
+
+		assert!(!sheet.is_gliding(&sheet.notes[c]));
+		sheet.set_bend(
+			b,
+			vec![BendPoint {
+				at: 1.0,
+				offset: Interval::Ratio(9, 8),
+			}],
+		);
+		assert!(sheet.is_gliding(&sheet.notes[c]));
+		sheet.set_bend(b, vec![]);
+		assert!(!sheet.is_gliding(&sheet.notes[c]));
+
+		// removing a root leaves the notes relative to it where they were
+		sheet.remove_note(b);
+		assert_eq!(sheet.notes[c].pitch, Pitch::Absolute(800.0));
+		sheet.move_note(a, 0.0, 8.0);
+		assert_eq!(freq(&sheet, c), 800.0);
+	}
+
+	#[test]
+	fn notes_on_degrees_follow_the_layout() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note {
+			pitch: Pitch::Degree { step: 7, octave: 0 },
+			..Note::new(Point::new(0.0, 0.0), 1.0)
+		});
+		let b = sheet.add_note(Note {
+			pitch: Pitch::Relative(a, Interval::Ratio(5, 4)),
+			..Note::new(Point::new(1.0, 0.0), 1.0)
+		});
+		let freq = |sheet: &Sheet, idx: Index| sheet.get_freq(sheet.notes[idx].pitch, sheet.notes[idx].start);
+		let fifth = 440.0 * 2f32.powf(7.0 / 12.0);
+		assert_eq!(freq(&sheet, a), fifth);
+		assert_eq!(freq(&sheet, b), fifth * 1.25);
+
+		// retuning the scale moves them
+		let mut layout = sheet.layout().clone();
+		layout.markers[0].pattern.freq.as_mut().unwrap().base = 220.0;
+		sheet.set_layout(&layout);
+		assert_eq!(freq(&sheet, a), fifth / 2.0);
+		assert_eq!(freq(&sheet, b), fifth / 2.0 * 1.25);
+
+		// moving one keeps it on the scale as long as it lands on a degree
+		sheet.move_note(a, 2.0, 440f32.log2());
+		assert_eq!(sheet.notes[a].pitch, Pitch::Degree { step: 0, octave: 1 });
+		sheet.move_note(a, 2.0, 9.0);
+		assert_eq!(sheet.notes[a].pitch, Pitch::Absolute(512.0));
+		assert_eq!(freq(&sheet, b), 640.0);
+	}
+}
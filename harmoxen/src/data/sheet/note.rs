@@ -1,18 +1,47 @@
 use super::{Interval, Sheet};
-use crate::data::{Point, Rect};
+use crate::data::{icp, Point, Rect};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
 pub enum Pitch<I> {
 	Absolute(f32),
 	Relative(I, Interval),
+	Degree { step: i32, octave: i32 }, // of the layout at the start of the note
 }
 
+// a point of a note's pitch envelope, `at` beats after its start
 #[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct BendPoint {
+	pub at: f32,
+	pub offset: Interval,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Note<I> {
 	pub pitch: Pitch<I>,
 	pub start: f32,
 	pub length: f32,
+	#[serde(default = "default_velocity")]
+	pub velocity: f32,
+	#[serde(default = "default_pressure")]
+	pub pressure: f32,
+	#[serde(default = "default_timbre")]
+	pub timbre: f32,
+	#[serde(default)]
+	pub bend: Vec<BendPoint>, // sorted by time
+	#[serde(default)]
+	pub track: usize,
+}
+
+// needed by serde to open projects saved before notes had attributes
+fn default_velocity() -> f32 {
+	icp::DEFAULT_VELOCITY
+}
+fn default_pressure() -> f32 {
+	icp::DEFAULT_PRESSURE
+}
+fn default_timbre() -> f32 {
+	icp::DEFAULT_TIMBRE
 }
 
 #[allow(unused)]
@@ -22,12 +51,84 @@ impl<I> Note<I> {
 			start: pos.x,
 			length: note_len,
 			pitch: Pitch::Absolute(2f32.powf(pos.y)),
+			velocity: icp::DEFAULT_VELOCITY,
+			pressure: icp::DEFAULT_PRESSURE,
+			timbre: icp::DEFAULT_TIMBRE,
+			bend: vec![],
+			track: 0,
+		}
+	}
+
+	pub fn with_pitch<J>(&self, pitch: Pitch<J>) -> Note<J> {
+		Note {
+			pitch,
+			start: self.start,
+			length: self.length,
+			velocity: self.velocity,
+			pressure: self.pressure,
+			timbre: self.timbre,
+			bend: self.bend.clone(),
+			track: self.track,
 		}
 	}
 
 	pub fn end(&self) -> f32 {
 		self.start + self.length
 	}
+
+	pub fn is_bent(&self) -> bool {
+		!self.bend.is_empty()
+	}
+
+	// ratio by which the note's own envelope bends it, `time` beats after its start
+	// the envelope starts unbent, is linear in log-frequency between points, and holds its last value
+	pub fn bend_at(&self, time: f32) -> f32 {
+		let (mut prev_at, mut prev_y) = (0.0, 0.0);
+		for point in &self.bend {
+			let y = point.offset.to_f32().log2();
+			if time <= point.at {
+				if point.at <= prev_at {
+					return 2f32.powf(y);
+				}
+				let t = ((time - prev_at) / (point.at - prev_at)).max(0.0);
+				return 2f32.powf(prev_y + (y - prev_y) * t);
+			}
+			prev_at = point.at;
+			prev_y = y;
+		}
+		2f32.powf(prev_y)
+	}
+
+	// mirrors the envelope in time, so that it starts at its last offset and ends unbent
+	pub fn reverse_bend(&mut self) {
+		let last = match self.bend.last() {
+			Some(point) => point.offset,
+			None => return,
+		};
+		let length = self.length;
+		let mut bend: Vec<BendPoint> = self
+			.bend
+			.iter()
+			.map(|point| BendPoint {
+				at: length - point.at,
+				offset: point.offset,
+			})
+			.collect();
+		bend.push(BendPoint { at: 0.0, offset: last });
+		bend.push(BendPoint {
+			at: length,
+			offset: Interval::UNISON,
+		});
+		bend.sort_by(|a, b| a.at.partial_cmp(&b.at).unwrap());
+		self.bend = bend;
+	}
+
+	// adds a point to the envelope, replacing any existing point at the same time
+	pub fn set_bend_point(&mut self, point: BendPoint) {
+		self.bend.retain(|p| p.at != point.at);
+		let i = self.bend.iter().position(|p| p.at > point.at).unwrap_or(self.bend.len());
+		self.bend.insert(i, point);
+	}
 }
 
 use generational_arena::Index;
@@ -35,22 +136,79 @@ use generational_arena::Index;
 #[allow(unused)]
 impl Note<Index> {
 	pub fn y(&self, sheet: &Sheet) -> f32 {
-		sheet.get_freq(self.pitch).log2()
+		sheet.get_y(self.pitch, self.start)
 	}
 
 	pub fn start_pt(&self, sheet: &Sheet) -> Point {
-		Point::new(self.start, sheet.get_y(self.pitch))
+		Point::new(self.start, self.y(sheet))
 	}
 
 	pub fn end_pt(&self, sheet: &Sheet) -> Point {
-		Point::new(self.end(), sheet.get_y(self.pitch))
+		Point::new(self.end(), self.y(sheet))
 	}
 
 	pub fn rect(&self, sheet: &Sheet, note_height: f32) -> Rect {
-		let y = sheet.get_y(self.pitch);
+		let y = self.y(sheet);
 		Rect::from_points(
-			Point::new(self.start, sheet.get_y(self.pitch) - note_height / 2.0),
-			Point::new(self.end(), sheet.get_y(self.pitch) - note_height / 2.0),
+			Point::new(self.start, y - note_height / 2.0),
+			Point::new(self.end(), y - note_height / 2.0),
 		)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn bends_between_points() {
+		let mut note = Note::<usize>::new(Point::new(0.0, 8.0), 4.0);
+		assert_eq!(note.bend_at(2.0), 1.0);
+		note.set_bend_point(BendPoint {
+			at: 2.0,
+			offset: Interval::Ratio(4, 1),
+		});
+		note.set_bend_point(BendPoint {
+			at: 1.0,
+			offset: Interval::Ratio(2, 1),
+		});
+		assert_eq!(note.bend.iter().map(|p| p.at).collect::<Vec<_>>(), vec![1.0, 2.0]);
+		// linear in log-frequency, and held after the last point
+		assert_eq!(note.bend_at(0.5), 2f32.sqrt());
+		assert_eq!(note.bend_at(1.5), 8f32.sqrt());
+		assert_eq!(note.bend_at(3.0), 4.0);
+		note.set_bend_point(BendPoint {
+			at: 2.0,
+			offset: Interval::Cents(-1200.0),
+		});
+		assert_eq!(note.bend.len(), 2);
+		assert_eq!(note.bend_at(3.0), 0.5);
+	}
+
+	#[test]
+	fn reverses_bends() {
+		let mut note = Note::<usize>::new(Point::new(0.0, 8.0), 4.0);
+		note.bend = vec![
+			BendPoint {
+				at: 1.0,
+				offset: Interval::Ratio(2, 1),
+			},
+			BendPoint {
+				at: 3.0,
+				offset: Interval::Ratio(3, 2),
+			},
+		];
+		let forward: Vec<f32> = (0..=8).map(|i| note.bend_at(i as f32 * 0.5)).collect();
+		note.reverse_bend();
+		let backward: Vec<f32> = (0..=8).rev().map(|i| note.bend_at(i as f32 * 0.5)).collect();
+		for (a, b) in forward.iter().zip(&backward) {
+			assert!((a / b).log2().abs() < 1e-6, "{:?} {:?}", forward, backward);
+		}
+		assert!(note.bend.windows(2).all(|w| w[0].at <= w[1].at));
+
+		// an unbent note stays unbent
+		let mut note = Note::<usize>::new(Point::new(0.0, 8.0), 4.0);
+		note.reverse_bend();
+		assert!(!note.is_bent());
+	}
+}
@@ -0,0 +1,224 @@
+use super::Note;
+use crate::data::Range;
+use generational_arena::{Arena, Index};
+use rstar::{RTree, RTreeObject, AABB};
+use std::collections::{HashMap, HashSet};
+
+// notes by time span and pitch, so that queries only look at the notes they may hit
+// the pitch of a note depends on its roots and on the layout, so the sheet marks the notes that may have moved,
+// and they are put back in place before the next query
+#[derive(Debug, Clone, Default)]
+pub struct NoteIndex {
+	tree: RTree<Entry>,
+	entries: HashMap<Index, Entry>, // what each note was inserted in the tree as, to remove it
+	dirty: HashSet<Index>,
+	// notes sorted by start and by end, for the events of a time range
+	starts: Vec<(f32, Index)>,
+	ends: Vec<(f32, Index)>,
+}
+
+// a note as a horizontal segment, from its start to its end at the height of its pitch
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+	idx: Index,
+	x: Range,
+	y: f32,
+}
+
+impl RTreeObject for Entry {
+	type Envelope = AABB<[f32; 2]>;
+
+	fn envelope(&self) -> Self::Envelope {
+		AABB::from_corners([self.x.0, self.y], [self.x.1, self.y])
+	}
+}
+
+impl NoteIndex {
+	// the notes are only placed in the tree by the first refresh
+	pub fn build(notes: &Arena<Note>) -> NoteIndex {
+		let mut index = NoteIndex {
+			starts: notes.iter().map(|(idx, note)| (note.start, idx)).collect(),
+			ends: notes.iter().map(|(idx, note)| (note.end(), idx)).collect(),
+			dirty: notes.iter().map(|(idx, _)| idx).collect(),
+			..NoteIndex::default()
+		};
+		index.starts.sort_by(|a, b| cmp_time(a.0, b.0));
+		index.ends.sort_by(|a, b| cmp_time(a.0, b.0));
+		index
+	}
+
+	pub fn insert(&mut self, idx: Index, note: &Note) {
+		insert_sorted(&mut self.starts, note.start, idx);
+		insert_sorted(&mut self.ends, note.end(), idx);
+		self.dirty.insert(idx);
+	}
+
+	pub fn remove(&mut self, idx: Index, note: &Note) {
+		remove_sorted(&mut self.starts, note.start, idx);
+		remove_sorted(&mut self.ends, note.end(), idx);
+		if let Some(entry) = self.entries.remove(&idx) {
+			self.tree.remove(&entry);
+		}
+		self.dirty.remove(&idx);
+	}
+
+	// the span or the pitch of a note may have changed
+	pub fn mark_dirty(&mut self, idx: Index) {
+		self.dirty.insert(idx);
+	}
+
+	// puts the dirty notes back in place, given the span and the height of a note
+	pub fn refresh(&mut self, place: impl Fn(Index) -> Option<(Range, f32)>) {
+		if self.dirty.is_empty() {
+			return;
+		}
+		let dirty = std::mem::take(&mut self.dirty);
+		let placed = dirty.into_iter().filter_map(|idx| {
+			let (x, y) = place(idx)?;
+			// the tree doesn't deal with infinite heights, e.g. from a zero frequency
+			Some(Entry {
+				idx,
+				x,
+				y: y.max(-1e6).min(1e6),
+			})
+		});
+		if self.entries.is_empty() {
+			let entries: Vec<Entry> = placed.collect();
+			self.entries = entries.iter().map(|&entry| (entry.idx, entry)).collect();
+			self.tree = RTree::bulk_load(entries);
+		} else {
+			for entry in placed.collect::<Vec<_>>() {
+				if let Some(old) = self.entries.insert(entry.idx, entry) {
+					self.tree.remove(&old);
+				}
+				self.tree.insert(entry);
+			}
+		}
+	}
+
+	// notes whose span crosses a time range and whose height is within a range
+	pub fn in_area(&self, x: Range, y: Range) -> impl Iterator<Item = Index> + '_ {
+		let area = AABB::from_corners([x.0, y.0], [x.1, y.1]);
+		self.tree.locate_in_envelope_intersecting(&area).map(|entry| entry.idx)
+	}
+
+	pub fn during(&self, x: Range) -> impl Iterator<Item = Index> + '_ {
+		self.in_area(x, Range(f32::MIN, f32::MAX))
+	}
+
+	pub fn starting_in(&self, range: Range) -> impl Iterator<Item = Index> + '_ {
+		in_range(&self.starts, range.0, range.1).iter().map(|&(_, idx)| idx)
+	}
+
+	pub fn ending_in(&self, range: Range) -> impl Iterator<Item = Index> + '_ {
+		in_range(&self.ends, range.0, range.1).iter().map(|&(_, idx)| idx)
+	}
+
+	pub fn last_end(&self) -> Option<f32> {
+		self.ends.last().map(|&(end, _)| end)
+	}
+}
+
+fn in_range(list: &[(f32, Index)], from: f32, to: f32) -> &[(f32, Index)] {
+	let lo = partition(list, |t| t < from);
+	let hi = partition(list, |t| t <= to);
+	&list[lo..hi.max(lo)]
+}
+
+fn insert_sorted(list: &mut Vec<(f32, Index)>, time: f32, idx: Index) {
+	let pos = partition(list, |t| t <= time);
+	list.insert(pos, (time, idx));
+}
+
+fn remove_sorted(list: &mut Vec<(f32, Index)>, time: f32, idx: Index) {
+	let lo = partition(list, |t| t < time);
+	if let Some(pos) = list[lo..].iter().position(|&(_, i)| i == idx) {
+		list.remove(lo + pos);
+	}
+}
+
+// times that aren't numbers go last, validated sheets don't have any
+fn cmp_time(a: f32, b: f32) -> std::cmp::Ordering {
+	a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+// index of the first entry whose time doesn't satisfy the predicate
+fn partition(list: &[(f32, Index)], pred: impl Fn(f32) -> bool) -> usize {
+	let (mut lo, mut hi) = (0, list.len());
+	while lo < hi {
+		let mid = (lo + hi) / 2;
+		if pred(list[mid].0) {
+			lo = mid + 1;
+		} else {
+			hi = mid;
+		}
+	}
+	lo
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::{sheet::Pitch, Point};
+
+	#[test]
+	fn finds_notes_by_time_and_pitch() {
+		let mut notes = Arena::new();
+		let a = notes.insert(Note::new(Point::new(0.0, 8.0), 2.0));
+		let b = notes.insert(Note::new(Point::new(1.0, 9.0), 1.0));
+		let c = notes.insert(Note::new(Point::new(3.0, 8.0), 1.0));
+		let place = |notes: &Arena<Note>, idx: Index| {
+			let note: &Note = notes.get(idx)?;
+			match note.pitch {
+				Pitch::Absolute(freq) => Some((Range(note.start, note.end()), freq.log2())),
+				_ => None,
+			}
+		};
+		let mut index = NoteIndex::build(&notes);
+		index.refresh(|idx| place(&notes, idx));
+		let sorted = |it: &mut dyn Iterator<Item = Index>| {
+			let mut v: Vec<Index> = it.collect();
+			v.sort();
+			v
+		};
+		assert_eq!(sorted(&mut index.during(Range(1.5, 1.5))), vec![a, b]);
+		assert_eq!(sorted(&mut index.in_area(Range(0.0, 4.0), Range(7.5, 8.5))), vec![a, c]);
+		assert_eq!(sorted(&mut index.starting_in(Range(0.5, 3.0))), vec![b, c]);
+		assert_eq!(sorted(&mut index.ending_in(Range(0.0, 2.0))), vec![a, b]);
+		assert_eq!(index.last_end(), Some(4.0));
+
+		// a moved note is found at its new place once the index is refreshed
+		let old = notes[b].clone();
+		notes[b].start = 5.0;
+		notes[b].pitch = Pitch::Absolute(256.0);
+		index.remove(b, &old);
+		index.insert(b, &notes[b]);
+		index.refresh(|idx| place(&notes, idx));
+		assert_eq!(sorted(&mut index.in_area(Range(0.0, 2.0), Range(8.5, 9.5))), vec![]);
+		assert_eq!(sorted(&mut index.in_area(Range(0.0, 6.0), Range(7.5, 8.5))), vec![a, b, c]);
+		assert_eq!(index.last_end(), Some(6.0));
+
+		// a note whose root moved only changes height
+		notes[c].pitch = Pitch::Absolute(1024.0);
+		index.mark_dirty(c);
+		index.refresh(|idx| place(&notes, idx));
+		assert_eq!(sorted(&mut index.in_area(Range(0.0, 6.0), Range(9.5, 10.5))), vec![c]);
+
+		let old = notes.remove(a).unwrap();
+		index.remove(a, &old);
+		assert_eq!(sorted(&mut index.during(Range(0.0, 10.0))), vec![b, c]);
+		assert_eq!(index.starting_in(Range(0.0, 10.0)).collect::<Vec<_>>(), vec![c, b]);
+	}
+
+	#[test]
+	fn keeps_notes_without_a_valid_time() {
+		let mut notes = Arena::new();
+		let a = notes.insert(Note::new(Point::new(f32::NAN, 8.0), 1.0));
+		let b = notes.insert(Note::new(Point::new(1.0, 8.0), 1.0));
+		let c = notes.insert(Note::new(Point::new(0.0, 8.0), f32::NAN));
+		let index = NoteIndex::build(&notes);
+		assert_eq!(index.starting_in(Range(0.0, 2.0)).collect::<Vec<_>>(), vec![c, b]);
+		assert_eq!(index.ending_in(Range(0.0, 2.0)).collect::<Vec<_>>(), vec![b]);
+		assert!(index.starts.iter().any(|&(_, idx)| idx == a));
+	}
+}
@@ -0,0 +1,42 @@
+use super::{Index, Interval, Sheet};
+
+impl Sheet {
+	// intervals from a base note to other notes, folded into a period, sorted and ending with the period
+	// they are exact for notes relative to the same root as the base, in ratios other ones get the simplest one within a tolerance
+	pub fn get_scale(&self, notes: &[Index], base: Index, period: Interval, tolerance: f32, cents: bool) -> Vec<Interval> {
+		let base_note = &self.notes[base];
+		let base_freq = self.get_freq(base_note.pitch, base_note.start);
+		let (base_root, base_interval) = self.get_root_interval(base_note.pitch).unwrap_or((base, Interval::UNISON));
+		let mut intervals = vec![];
+		for &idx in notes {
+			let note = &self.notes[idx];
+			let interval = match self.get_root_interval(note.pitch).unwrap_or((idx, Interval::UNISON)) {
+				(root, interval) if root == base_root => interval * base_interval.inverse(),
+				_ => {
+					let ratio = self.get_freq(note.pitch, note.start) / base_freq;
+					let cents_interval = Interval::Cents(1200.0 * ratio.log2());
+					if cents {
+						cents_interval
+					} else {
+						Interval::approximate(ratio, tolerance).unwrap_or(cents_interval)
+					}
+				}
+			};
+			let interval = interval.reduce(period);
+			// other kinds of intervals can't be typed back as easily
+			intervals.push(match interval.to_ratio_interval() {
+				Some(ratio) if !cents => ratio,
+				_ => interval.to_cents_interval(),
+			});
+		}
+		intervals.sort_by(|a, b| a.to_f64().partial_cmp(&b.to_f64()).unwrap());
+		intervals.dedup_by(|a, b| (a.to_cents() - b.to_cents()).abs() < 1e-3);
+		// the unison is the base itself
+		intervals.retain(|x| x.to_cents().abs() > 1e-3);
+		intervals.push(match period.to_ratio_interval() {
+			Some(ratio) if !cents => ratio,
+			_ => period.to_cents_interval(),
+		});
+		intervals
+	}
+}
@@ -0,0 +1,174 @@
+use super::{interval::gcd, Index, Interval, Sheet};
+use crate::data::Range;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+// odd numbers up to which the ratios of a prime limit are looked for
+const PRIME_LIMIT_BOUND: usize = 81;
+
+// which ratios harmonic snapping may use
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Limit {
+	#[display(fmt = "{}-odd-limit", _0)]
+	Odd(usize),
+	#[display(fmt = "{}-prime-limit", _0)]
+	Prime(usize),
+}
+
+impl Limit {
+	pub const ALL: [Limit; 7] = [
+		Limit::Odd(5),
+		Limit::Odd(7),
+		Limit::Odd(9),
+		Limit::Odd(15),
+		Limit::Prime(5),
+		Limit::Prime(7),
+		Limit::Prime(11),
+	];
+
+	fn allows(&self, n: usize) -> bool {
+		match *self {
+			Limit::Odd(limit) => n <= limit,
+			Limit::Prime(limit) => n <= PRIME_LIMIT_BOUND && largest_prime_factor(n) <= limit,
+		}
+	}
+
+	// ratios of the limit, reduced to the octave [1, 2)
+	pub fn ratios(&self) -> Vec<(usize, usize)> {
+		let bound = match *self {
+			Limit::Odd(limit) => limit,
+			Limit::Prime(_) => PRIME_LIMIT_BOUND,
+		};
+		let odds: Vec<usize> = (1..=bound).step_by(2).filter(|&n| self.allows(n)).collect();
+		let mut ratios = vec![];
+		for &a in &odds {
+			for &b in &odds {
+				if gcd(a, b) != 1 {
+					continue;
+				}
+				let (mut num, mut denom) = (a, b);
+				while num >= 2 * denom {
+					denom *= 2;
+				}
+				while num < denom {
+					num *= 2;
+				}
+				ratios.push((num, denom));
+			}
+		}
+		ratios
+	}
+}
+
+// a frequency at a simple ratio from a sounding note
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snap {
+	pub root: Index,
+	pub interval: Interval,
+	pub freq: f32,
+}
+
+impl Sheet {
+	// closest frequency making a ratio of the limit with a note sounding during the range
+	pub fn snap_freq<F>(&self, freq: f32, range: Range, limit: Limit, exclude: F) -> Option<Snap>
+	where
+		F: Fn(Index) -> bool,
+	{
+		let ratios = limit.ratios();
+		let mut best: Option<(f32, Snap)> = None; // distance in octaves
+		for root in self.get_notes_during(range) {
+			if exclude(root) {
+				continue;
+			}
+			let root_freq = self.get_freq(self.notes[root].pitch, self.notes[root].start);
+			for &(num, denom) in &ratios {
+				let ratio = num as f32 / denom as f32;
+				let octave = (freq / root_freq / ratio).log2().round().max(-16.0).min(16.0) as i32;
+				let target = root_freq * ratio * 2f32.powi(octave);
+				let distance = (target / freq).log2().abs();
+				if best.map_or(true, |(d, _)| distance < d) {
+					let (num, denom) = if octave >= 0 {
+						(num << octave, denom)
+					} else {
+						(num, denom << -octave)
+					};
+					let d = gcd(num, denom);
+					let interval = Interval::Ratio(num / d, denom / d);
+					best = Some((
+						distance,
+						Snap {
+							root,
+							interval,
+							freq: target,
+						},
+					));
+				}
+			}
+		}
+		best.map(|(_, snap)| snap)
+	}
+}
+
+fn largest_prime_factor(mut n: usize) -> usize {
+	let mut largest = 1;
+	let mut p = 2;
+	while p * p <= n {
+		while n % p == 0 {
+			n /= p;
+			largest = p;
+		}
+		p += 1;
+	}
+	largest.max(n)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::{
+		sheet::{Note, Pitch},
+		Point,
+	};
+
+	#[test]
+	fn lists_the_ratios_of_a_limit() {
+		let mut ratios = Limit::Odd(5).ratios();
+		ratios.sort();
+		assert_eq!(ratios, vec![(1, 1), (3, 2), (4, 3), (5, 3), (5, 4), (6, 5), (8, 5)]);
+		let ratios = Limit::Prime(5).ratios();
+		assert!(ratios.contains(&(81, 80)));
+		assert!(ratios.contains(&(45, 32)));
+		assert!(!ratios.contains(&(7, 4)));
+		for &(num, denom) in &ratios {
+			assert!(num >= denom && num < 2 * denom, "{}/{}", num, denom);
+			assert_eq!(gcd(num, denom), 1);
+			assert!(largest_prime_factor(num * denom) <= 5);
+		}
+		assert_eq!(largest_prime_factor(1), 1);
+		assert_eq!(largest_prime_factor(81), 3);
+		assert_eq!(largest_prime_factor(77), 11);
+	}
+
+	#[test]
+	fn snaps_to_sounding_notes() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note::new(Point::new(0.0, 8.0), 1.0));
+		let snap = |freq: f32, range: Range| sheet.snap_freq(freq, range, Limit::Odd(5), |_| false);
+		let fifth = snap(383.0, Range(0.5, 1.5)).unwrap();
+		assert_eq!((fifth.root, fifth.interval, fifth.freq), (a, Interval::Ratio(3, 2), 384.0));
+		// in other octaves
+		assert_eq!(snap(770.0, Range(0.5, 1.5)).unwrap().interval, Interval::Ratio(3, 1));
+		assert_eq!(snap(170.0, Range(0.5, 1.5)).unwrap().interval, Interval::Ratio(2, 3));
+		assert_eq!(snap(383.0, Range(2.0, 3.0)), None);
+		assert_eq!(sheet.snap_freq(383.0, Range(0.5, 1.5), Limit::Odd(5), |idx| idx == a), None);
+
+		// from notes on a degree, the default layout being 12-EDO from A4
+		let mut sheet = Sheet::default();
+		let b = sheet.add_note(Note {
+			pitch: Pitch::Degree { step: 0, octave: 0 },
+			..Note::new(Point::new(0.0, 0.0), 1.0)
+		});
+		let third = sheet.snap_freq(555.0, Range(0.0, 1.0), Limit::Odd(5), |_| false).unwrap();
+		assert_eq!((third.root, third.interval, third.freq), (b, Interval::Ratio(5, 4), 550.0));
+	}
+}
@@ -0,0 +1,278 @@
+use super::{Index, Interval, Note, Pitch, Sheet};
+use crate::data::Range;
+use std::collections::HashSet;
+
+// bulk edits applied to a selection of notes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+	Transpose(Interval),
+	Stretch { origin: f32, factor: f32 },
+	Retrograde,
+	Invert(f32), // axis frequency
+	Quantize,
+	Relativize(f32), // tolerance in cents
+}
+
+// how long before a note, in beats, a root can be looked for
+const ROOT_SEARCH_WINDOW: f32 = 1.0;
+
+impl Sheet {
+	// relative notes keep their intervals, the pitch changes are applied to their absolute roots
+	// notes of the `hidden` tracks aren't used as roots
+	// returns the notes that were changed
+	pub fn transform(&mut self, notes: &HashSet<Index>, transform: Transform, hidden: &HashSet<usize>) -> Vec<Index> {
+		let mut notes: Vec<Index> = notes.iter().copied().filter(|&idx| self.notes.contains(idx)).collect();
+		let roots = match transform {
+			Transform::Transpose(interval) => self.map_roots(&notes, false, |_, freq| freq * interval),
+			Transform::Invert(axis) => self.map_roots(&notes, false, |_, freq| axis * axis / freq),
+			Transform::Stretch { origin, factor } => {
+				for &idx in &notes {
+					self.retime_note(idx, |note| {
+						note.start = origin + (note.start - origin) * factor;
+						note.length *= factor;
+						for point in &mut note.bend {
+							point.at *= factor;
+						}
+					});
+				}
+				vec![]
+			}
+			Transform::Retrograde => {
+				let (first, last) = notes.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |acc, &idx| {
+					let note = &self.notes[idx];
+					(acc.0.min(note.start), acc.1.max(note.end()))
+				});
+				for &idx in &notes {
+					self.retime_note(idx, |note| {
+						note.start = first + last - note.end();
+						note.reverse_bend();
+					});
+				}
+				vec![]
+			}
+			Transform::Quantize => {
+				let layout = self.layout().clone();
+				for &idx in &notes {
+					self.retime_note(idx, |note| {
+						let quantized = layout.quantize_note(note.with_pitch(Pitch::Absolute(0.0)));
+						note.start = quantized.start;
+						note.length = quantized.length;
+					});
+				}
+				self.map_roots(&notes, true, |_, freq| freq)
+			}
+			Transform::Relativize(tolerance) => {
+				// selected notes are only attached to the ones before them, so the first one stays absolute
+				let key = |sheet: &Sheet, idx: Index| (sheet.notes[idx].start, sheet.notes[idx].y(sheet));
+				notes.sort_by(|&a, &b| key(self, a).partial_cmp(&key(self, b)).unwrap());
+				let mut changed = vec![];
+				for i in 0..notes.len() {
+					if self.relativize(notes[i], &notes[i + 1..], tolerance, hidden) {
+						changed.push(notes[i]);
+					}
+				}
+				notes = changed;
+				vec![]
+			}
+		};
+		for root in roots {
+			if !notes.contains(&root) {
+				notes.push(root);
+			}
+		}
+		notes
+	}
+
+	// attaches an absolute note to the nearby note it makes the simplest ratio with
+	fn relativize(&mut self, idx: Index, exclude: &[Index], tolerance: f32, hidden: &HashSet<usize>) -> bool {
+		let note = &self.notes[idx];
+		let freq = match note.pitch {
+			Pitch::Absolute(_) | Pitch::Degree { .. } => self.get_freq(note.pitch, note.start),
+			Pitch::Relative(..) => return false,
+		};
+		let (start, end) = (note.start.min(note.end()), note.start.max(note.end()));
+		let mut best: Option<(f32, f32, Pitch)> = None; // height, distance, pitch
+		for root in self.get_notes_during(Range(start - ROOT_SEARCH_WINDOW, end)) {
+			if root == idx || exclude.contains(&root) || hidden.contains(&self.notes[root].track) {
+				continue;
+			}
+			let root_freq = self.get_freq(self.notes[root].pitch, self.notes[root].start);
+			let interval = match Interval::approximate(freq / root_freq, tolerance) {
+				Some(interval) => interval,
+				None => continue,
+			};
+			let pitch = Pitch::Relative(root, interval);
+			if self.check_pitch(Some(idx), pitch).is_err() {
+				continue;
+			}
+			let height = interval.height().unwrap_or(f32::INFINITY);
+			let distance = (start - self.notes[root].start.max(self.notes[root].end())).max(0.0);
+			if best.map_or(true, |(h, d, _)| (height, distance) < (h, d)) {
+				best = Some((height, distance, pitch));
+			}
+		}
+		match best {
+			Some((_, _, pitch)) => self.set_pitch(idx, pitch).is_ok(),
+			None => false,
+		}
+	}
+
+	// changes the frequency of every absolute root of the notes, each one once
+	// roots on a degree stay on one if they land on the scale, all of them go to the nearest degree if `bind` is set
+	fn map_roots<F>(&mut self, notes: &[Index], bind: bool, f: F) -> Vec<Index>
+	where
+		F: Fn(f32, f32) -> f32, // start and frequency of the root
+	{
+		let mut roots = vec![];
+		for &idx in notes {
+			let root = self.get_root_interval(self.notes[idx].pitch).map_or(idx, |(root, _)| root);
+			if !roots.contains(&root) {
+				roots.push(root);
+			}
+		}
+		for &root in &roots {
+			let Note { pitch, start, .. } = self.notes[root];
+			if let Pitch::Relative(..) = pitch {
+				continue;
+			}
+			let freq = f(start, self.get_freq(pitch, start));
+			let pitch = match pitch {
+				_ if bind => self.layout().nearest_degree(start, freq),
+				Pitch::Degree { .. } => self.layout().degree_on(start, freq),
+				_ => None,
+			};
+			self.invalidate(root);
+			self.notes[root].pitch = pitch.unwrap_or(Pitch::Absolute(freq));
+		}
+		roots
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::data::{sheet::BendPoint, Point};
+
+	#[test]
+	fn transposes_roots_and_keeps_intervals() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note::new(Point::new(0.0, 8.0), 1.0));
+		let b = sheet.add_note(Note {
+			pitch: Pitch::Relative(a, Interval::Ratio(3, 2)),
+			..Note::new(Point::new(1.0, 0.0), 1.0)
+		});
+		let selection = [b].iter().copied().collect();
+		let changed = sheet.transform(&selection, Transform::Transpose(Interval::Ratio(5, 4)), &HashSet::new());
+		assert_eq!(changed.len(), 2);
+		assert_eq!(sheet.notes[a].pitch, Pitch::Absolute(320.0));
+		assert_eq!(sheet.notes[b].pitch, Pitch::Relative(a, Interval::Ratio(3, 2)));
+		assert_eq!(sheet.get_freq(sheet.notes[b].pitch, 1.0), 480.0);
+
+		let selection = [a, b].iter().copied().collect();
+		sheet.transform(&selection, Transform::Invert(400.0), &HashSet::new());
+		assert_eq!(sheet.notes[a].pitch, Pitch::Absolute(500.0));
+	}
+
+	#[test]
+	fn degrees_stay_on_the_scale() {
+		// the default layout is 12-EDO from A4
+		let mut sheet = Sheet::default();
+		let degree = |step| Note {
+			pitch: Pitch::Degree { step, octave: 0 },
+			..Note::new(Point::new(0.0, 0.0), 1.0)
+		};
+		let a = sheet.add_note(degree(0));
+		let selection = [a].iter().copied().collect();
+		let hidden = HashSet::new();
+		sheet.transform(&selection, Transform::Transpose(Interval::Edo(7, 12, 2)), &hidden);
+		assert_eq!(sheet.notes[a].pitch, Pitch::Degree { step: 7, octave: 0 });
+		sheet.transform(&selection, Transform::Transpose(Interval::Ratio(2, 1)), &hidden);
+		assert_eq!(sheet.notes[a].pitch, Pitch::Degree { step: 7, octave: 1 });
+		sheet.transform(&selection, Transform::Invert(440.0), &hidden);
+		assert_eq!(sheet.notes[a].pitch, Pitch::Degree { step: 5, octave: -2 });
+
+		// off the scale, the note keeps the exact frequency
+		let b = sheet.add_note(degree(0));
+		let selection = [b].iter().copied().collect();
+		sheet.transform(&selection, Transform::Transpose(Interval::Ratio(81, 80)), &hidden);
+		match sheet.notes[b].pitch {
+			Pitch::Absolute(freq) => assert!((freq - 445.5).abs() < 1e-3),
+			pitch => panic!("{:?}", pitch),
+		}
+		sheet.transform(&selection, Transform::Transpose(Interval::Ratio(80, 81)), &hidden);
+		assert!(matches!(sheet.notes[b].pitch, Pitch::Absolute(_)));
+		// until it is quantized
+		sheet.transform(&selection, Transform::Quantize, &hidden);
+		assert_eq!(sheet.notes[b].pitch, Pitch::Degree { step: 0, octave: 0 });
+	}
+
+	#[test]
+	fn retimes_notes() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note::new(Point::new(0.0, 8.0), 1.0));
+		let b = sheet.add_note(Note::new(Point::new(2.0, 8.0), 2.0));
+		sheet.set_bend(
+			b,
+			vec![BendPoint {
+				at: 1.0,
+				offset: Interval::Ratio(9, 8),
+			}],
+		);
+		let selection = [a, b].iter().copied().collect();
+		let hidden = HashSet::new();
+		sheet.transform(
+			&selection,
+			Transform::Stretch {
+				origin: 1.0,
+				factor: 2.0,
+			},
+			&hidden,
+		);
+		assert_eq!((sheet.notes[a].start, sheet.notes[a].length), (-1.0, 2.0));
+		assert_eq!((sheet.notes[b].start, sheet.notes[b].length), (3.0, 4.0));
+		assert_eq!(sheet.notes[b].bend[0].at, 2.0);
+
+		sheet.transform(&selection, Transform::Retrograde, &hidden);
+		assert_eq!(sheet.notes[a].start, 5.0);
+		assert_eq!(sheet.notes[b].start, -1.0);
+		// the envelope is played backwards
+		assert_eq!(sheet.notes[b].bend_at(0.0), 9.0 / 8.0);
+		assert_eq!(sheet.notes[b].bend_at(4.0), 1.0);
+		assert_eq!(sheet.get_notes_during(Range(5.5, 5.5)), vec![a]);
+	}
+
+	#[test]
+	fn attaches_notes_to_the_simplest_root() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note::new(Point::new(0.0, 8.0), 1.0));
+		let b = sheet.add_note(Note::new(Point::new(0.0, 320f32.log2()), 1.0));
+		let c = sheet.add_note(Note::new(Point::new(0.5, 480f32.log2()), 1.0));
+		// too far before the note to be a root
+		let d = sheet.add_note(Note::new(Point::new(-3.0, 8.0), 1.0));
+		let e = sheet.add_note(Note::new(Point::new(3.0, 9.0), 1.0));
+		let selection = [b, c, e].iter().copied().collect();
+		let changed = sheet.transform(&selection, Transform::Relativize(1.0), &HashSet::new());
+		assert_eq!(sheet.notes[b].pitch, Pitch::Relative(a, Interval::Ratio(5, 4)));
+		// 3/2 from b is simpler than 15/8 from a
+		assert_eq!(sheet.notes[c].pitch, Pitch::Relative(b, Interval::Ratio(3, 2)));
+		assert_eq!(sheet.notes[e].pitch, Pitch::Absolute(512.0));
+		assert_eq!(changed.len(), 2);
+		assert_eq!(sheet.notes[d].pitch, Pitch::Absolute(256.0));
+	}
+
+	#[test]
+	fn attaches_notes_to_visible_roots_only() {
+		let mut sheet = Sheet::default();
+		let a = sheet.add_note(Note {
+			track: 1,
+			..Note::new(Point::new(0.0, 8.0), 1.0)
+		});
+		let b = sheet.add_note(Note::new(Point::new(0.0, 9.0), 1.0));
+		let selection = [b].iter().copied().collect();
+		let hidden = [1].iter().copied().collect();
+		assert!(sheet.transform(&selection, Transform::Relativize(1.0), &hidden).is_empty());
+		assert_eq!(sheet.notes[b].pitch, Pitch::Absolute(512.0));
+		sheet.transform(&selection, Transform::Relativize(1.0), &HashSet::new());
+		assert_eq!(sheet.notes[b].pitch, Pitch::Relative(a, Interval::Ratio(2, 1)));
+	}
+}
@@ -0,0 +1,237 @@
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+// how the tempo reaches a tempo change from the previous one
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Ramp {
+	Step,
+	Linear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct TempoChange {
+	pub at: f32, // in beats
+	pub bpm: f32,
+	pub ramp: Ramp,
+}
+
+// converts between beats and seconds, everything that plays the sheet should go through it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TempoMap {
+	pub initial: f32,
+	pub changes: Vec<TempoChange>, // sorted by time
+}
+
+impl Default for TempoMap {
+	fn default() -> TempoMap {
+		TempoMap::new(120.0)
+	}
+}
+
+// a part of the map where the tempo goes linearly from `bpm.0` to `bpm.1`
+struct Segment {
+	start: f64,
+	end: f64,
+	bpm: (f64, f64),
+}
+
+impl Segment {
+	fn slope(&self) -> f64 {
+		if self.end.is_finite() && self.end > self.start && self.bpm.0 != self.bpm.1 {
+			(self.bpm.1 - self.bpm.0) / (self.end - self.start)
+		} else {
+			0.0
+		}
+	}
+
+	// seconds elapsed from the start of the segment to a beat inside it
+	fn seconds_to(&self, beat: f64) -> f64 {
+		let slope = self.slope();
+		let beats = beat - self.start;
+		if slope == 0.0 {
+			beats * 60.0 / self.bpm.0
+		} else {
+			60.0 / slope * ((self.bpm.0 + slope * beats) / self.bpm.0).ln()
+		}
+	}
+
+	// beat reached after some seconds from the start of the segment
+	fn beat_after(&self, seconds: f64) -> f64 {
+		let slope = self.slope();
+		if slope == 0.0 {
+			self.start + seconds * self.bpm.0 / 60.0
+		} else {
+			self.start + self.bpm.0 * ((slope * seconds / 60.0).exp() - 1.0) / slope
+		}
+	}
+}
+
+impl TempoMap {
+	pub fn new(initial: f32) -> TempoMap {
+		TempoMap {
+			initial,
+			changes: vec![],
+		}
+	}
+
+	fn segments(&self) -> Vec<Segment> {
+		let mut segments = vec![];
+		let (mut start, mut bpm) = (0.0, self.initial as f64);
+		for change in &self.changes {
+			let end = change.at as f64;
+			let next = change.bpm as f64;
+			segments.push(Segment {
+				start,
+				end,
+				bpm: (bpm, if change.ramp == Ramp::Linear { next } else { bpm }),
+			});
+			start = end;
+			bpm = next;
+		}
+		segments.push(Segment {
+			start,
+			end: f64::INFINITY,
+			bpm: (bpm, bpm),
+		});
+		segments
+	}
+
+	pub fn tempo_at(&self, beat: f32) -> f32 {
+		let beat = beat as f64;
+		for segment in self.segments() {
+			if beat < segment.end {
+				let bpm = segment.bpm.0 + segment.slope() * (beat - segment.start).max(0.0);
+				return bpm as f32;
+			}
+		}
+		self.initial
+	}
+
+	pub fn to_seconds(&self, beat: f32) -> f32 {
+		self.seconds_at(beat as f64) as f32
+	}
+
+	pub fn to_beats(&self, seconds: f32) -> f32 {
+		self.beat_at(seconds as f64) as f32
+	}
+
+	// beat reached after playing for some seconds from a beat
+	pub fn advance(&self, beat: f32, seconds: f32) -> f32 {
+		self.beat_at(self.seconds_at(beat as f64) + seconds as f64) as f32
+	}
+
+	// seconds needed to play from a beat to another
+	pub fn duration(&self, from: f32, to: f32) -> f32 {
+		(self.seconds_at(to as f64) - self.seconds_at(from as f64)) as f32
+	}
+
+	fn seconds_at(&self, beat: f64) -> f64 {
+		let mut seconds = 0.0;
+		for segment in self.segments() {
+			if beat < segment.end {
+				return seconds + segment.seconds_to(beat);
+			}
+			seconds += segment.seconds_to(segment.end);
+		}
+		seconds
+	}
+
+	fn beat_at(&self, seconds: f64) -> f64 {
+		let mut remaining = seconds;
+		for segment in self.segments() {
+			let length = segment.seconds_to(segment.end);
+			if remaining < length {
+				return segment.beat_after(remaining);
+			}
+			remaining -= length;
+		}
+		0.0
+	}
+
+	pub fn add_change(&mut self, change: TempoChange) -> usize {
+		let idx = self
+			.changes
+			.iter()
+			.position(|c| c.at > change.at)
+			.unwrap_or(self.changes.len());
+		self.changes.insert(idx, change);
+		idx
+	}
+
+	// returns the new index of the change, which moves when it is dragged past another one
+	pub fn set_change(&mut self, idx: usize, change: TempoChange) -> usize {
+		self.changes.remove(idx);
+		self.add_change(change)
+	}
+
+	pub fn remove_change(&mut self, idx: usize) {
+		self.changes.remove(idx);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn close(a: f32, b: f32) -> bool {
+		(a - b).abs() < 1e-4
+	}
+
+	#[test]
+	fn constant_tempo() {
+		let map = TempoMap::new(120.0);
+		assert!(close(map.to_seconds(4.0), 2.0));
+		assert!(close(map.to_beats(2.0), 4.0));
+		assert!(close(map.advance(1.0, 0.5), 2.0));
+	}
+
+	#[test]
+	fn step_change() {
+		let mut map = TempoMap::new(120.0);
+		map.add_change(TempoChange {
+			at: 4.0,
+			bpm: 60.0,
+			ramp: Ramp::Step,
+		});
+		assert!(close(map.tempo_at(3.9), 120.0));
+		assert!(close(map.tempo_at(4.0), 60.0));
+		assert!(close(map.to_seconds(4.0), 2.0));
+		assert!(close(map.to_seconds(6.0), 4.0));
+		assert!(close(map.to_beats(4.0), 6.0));
+		assert!(close(map.duration(3.0, 5.0), 1.5));
+	}
+
+	#[test]
+	fn linear_ramp() {
+		let mut map = TempoMap::new(60.0);
+		map.add_change(TempoChange {
+			at: 4.0,
+			bpm: 120.0,
+			ramp: Ramp::Linear,
+		});
+		assert!(close(map.tempo_at(2.0), 90.0));
+		assert!(close(map.tempo_at(5.0), 120.0));
+		// the ramp takes 60 / slope * ln(120 / 60) seconds, with a slope of 15 bpm per beat
+		let ramp = 4.0 * 2f32.ln();
+		assert!(close(map.to_seconds(4.0), ramp));
+		assert!(close(map.to_seconds(6.0), ramp + 1.0));
+		for &beat in &[0.5, 2.0, 3.99, 4.0, 7.25] {
+			assert!(close(map.to_beats(map.to_seconds(beat)), beat), "{}", beat);
+		}
+		assert!(close(map.advance(2.0, map.duration(2.0, 5.0)), 5.0));
+	}
+
+	#[test]
+	fn changes_stay_sorted() {
+		let mut map = TempoMap::new(120.0);
+		let change = |at| TempoChange {
+			at,
+			bpm: 100.0,
+			ramp: Ramp::Step,
+		};
+		map.add_change(change(8.0));
+		assert_eq!(map.add_change(change(2.0)), 0);
+		assert_eq!(map.set_change(0, change(10.0)), 1);
+		assert_eq!(map.changes.iter().map(|c| c.at).collect::<Vec<_>>(), vec![8.0, 10.0]);
+	}
+}
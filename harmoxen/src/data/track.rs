@@ -0,0 +1,79 @@
+use crate::BackendId;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+// member channels of an MPE zone given to a track, from 0 (MIDI channel 2) to 14 (MIDI channel 16)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ChannelGroup {
+	pub first: u8,
+	pub last: u8,
+}
+
+impl ChannelGroup {
+	pub const ALL: ChannelGroup = ChannelGroup { first: 0, last: 14 };
+
+	pub fn contains(&self, channel: usize) -> bool {
+		self.first as usize <= channel && channel <= self.last as usize
+	}
+}
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Target {
+	#[display(fmt = "Audio")]
+	Audio,
+	#[display(fmt = "MIDI ch. {}-{}", "_0.first + 2", "_0.last + 2")]
+	Midi(ChannelGroup),
+}
+
+impl Target {
+	// where new tracks are sent with a backend
+	pub fn of_backend(backend: &BackendId) -> Target {
+		match backend {
+			BackendId::Audio => Target::Audio,
+			BackendId::Midi(_) => Target::Midi(ChannelGroup::ALL),
+		}
+	}
+
+	pub const ALL: [Target; 4] = [
+		Target::Audio,
+		Target::Midi(ChannelGroup::ALL),
+		Target::Midi(ChannelGroup { first: 0, last: 6 }),
+		Target::Midi(ChannelGroup { first: 7, last: 14 }),
+	];
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Track {
+	pub name: String,
+	pub mute: bool,
+	pub solo: bool,
+	pub visible: bool,
+	pub target: Target,
+}
+
+impl Track {
+	pub fn new(name: String) -> Track {
+		Track {
+			name,
+			mute: false,
+			solo: false,
+			visible: true,
+			target: Target::Audio,
+		}
+	}
+}
+
+impl Default for Track {
+	fn default() -> Track {
+		Track::new("Track 1".into())
+	}
+}
+
+// which tracks should be heard on an output, taking mute and solo into account
+pub fn routed(tracks: &[Track], f: impl Fn(Target) -> bool) -> Vec<bool> {
+	let solo = tracks.iter().any(|track| track.solo);
+	tracks
+		.iter()
+		.map(|track| !track.mute && (track.solo || !solo) && f(track.target))
+		.collect()
+}
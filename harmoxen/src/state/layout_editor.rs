@@ -6,18 +6,28 @@ use crate::data::layout::{
 use crate::state::Message as RootMessage;
 use iced::{button, text_input, Command};
 
-use crate::data::layout::Pattern;
+use crate::data::layout::{parse_field, PatternInput};
+use crate::data::sheet::Interval;
 
 #[derive(Clone, Default)]
 pub struct State {
 	pub close_btn_state: button::State,
 	pub apply_btn_state: button::State,
+	pub load_btn_state: button::State,
 	pub time_pick_list: iced::pick_list::State<time_input::Mode>,
 	pub wstates_time: [text_input::State; 3],
 	pub time: TimeInput,
 	pub freq_pick_list: iced::pick_list::State<freq_input::Mode>,
-	pub wstates_freq: [text_input::State; 3],
+	pub wstates_freq: [text_input::State; 5],
 	pub freq: FreqInput,
+	pub load_kbm_btn_state: button::State,
+	pub clear_kbm_btn_state: button::State,
+	pub kbm: Option<String>,
+	pub morph_input_state: text_input::State,
+	pub morph: String,
+	pub transpose_input_state: text_input::State,
+	pub transpose: String,
+	pub error: Option<LayoutParseError>,
 }
 
 impl State {
@@ -58,6 +68,13 @@ impl State {
 					freq_input::Mode::Equal => FreqInput::default_equal(),
 					freq_input::Mode::Enumeration => FreqInput::default_enumeration(),
 					freq_input::Mode::HarmonicSegment => FreqInput::default_harmonic_segment(),
+					freq_input::Mode::Scala => FreqInput::default_scala(),
+					freq_input::Mode::Generated => FreqInput::default_generated(),
+					freq_input::Mode::SubharmonicSegment => FreqInput::default_subharmonic_segment(),
+					freq_input::Mode::Cps => FreqInput::default_cps(),
+					freq_input::Mode::Diamond => FreqInput::default_diamond(),
+					freq_input::Mode::Formula => FreqInput::default_formula(),
+					freq_input::Mode::Intervals => FreqInput::default_intervals(),
 				}
 			}
 			Message::SetFreqField(idx, text) => match &mut self.freq {
@@ -69,7 +86,7 @@ impl State {
 					2 => *ndiv = text,
 					_ => {}
 				},
-				FreqInput::Enumeration { base, values } => match idx {
+				FreqInput::Enumeration { base, values } | FreqInput::Intervals { base, values } => match idx {
 					0 => *base = text,
 					1 => *values = text,
 					_ => {}
@@ -80,7 +97,83 @@ impl State {
 					2 => *to = text,
 					_ => {}
 				},
+				FreqInput::Scala { base, .. } => match idx {
+					0 => *base = text,
+					_ => {}
+				},
+				FreqInput::Generated {
+					base,
+					period,
+					generator,
+					size,
+					down,
+				} => match idx {
+					0 => *base = text,
+					1 => *period = text,
+					2 => *generator = text,
+					3 => *size = text,
+					4 => *down = text,
+					_ => {}
+				},
+				FreqInput::SubharmonicSegment { base, from, to } => match idx {
+					0 => *base = text,
+					1 => *from = text,
+					2 => *to = text,
+					_ => {}
+				},
+				FreqInput::Cps {
+					base,
+					factors,
+					choose,
+					period,
+				} => match idx {
+					0 => *base = text,
+					1 => *factors = text,
+					2 => *choose = text,
+					3 => *period = text,
+					_ => {}
+				},
+				FreqInput::Diamond { base, limit, period } => match idx {
+					0 => *base = text,
+					1 => *limit = text,
+					2 => *period = text,
+					_ => {}
+				},
+				FreqInput::Formula {
+					base,
+					count,
+					period,
+					formula,
+				} => match idx {
+					0 => *base = text,
+					1 => *count = text,
+					2 => *period = text,
+					3 => *formula = text,
+					_ => {}
+				},
 			},
+			Message::LoadScl => {
+				if let FreqInput::Scala { scl, .. } = &mut self.freq {
+					if let Some(path) = rfd::FileDialog::new().add_filter("scl", &["scl"]).pick_file() {
+						// scala files are often latin-1, which only matters in comments and descriptions
+						match std::fs::read(&path) {
+							Ok(bytes) => *scl = String::from_utf8_lossy(&bytes).into_owned(),
+							Err(e) => println!("could not read {:?}: {}", path, e),
+						}
+					}
+				}
+			}
+			Message::LoadKbm => {
+				if let Some(path) = rfd::FileDialog::new().add_filter("kbm", &["kbm"]).pick_file() {
+					match std::fs::read(&path) {
+						Ok(bytes) => self.kbm = Some(String::from_utf8_lossy(&bytes).into_owned()),
+						Err(e) => println!("could not read {:?}: {}", path, e),
+					}
+				}
+			}
+			Message::ClearKbm => self.kbm = None,
+			Message::SetMorph(text) => self.morph = text,
+			Message::SetTranspose(text) => self.transpose = text,
 		}
 		Command::none()
 	}
@@ -92,6 +185,11 @@ pub enum Message {
 	SetTimeField(usize, String),
 	SetFreqMode(freq_input::Mode),
 	SetFreqField(usize, String),
+	LoadScl,
+	LoadKbm,
+	ClearKbm,
+	SetMorph(String),
+	SetTranspose(String),
 }
 
 impl From<Message> for RootMessage {
@@ -100,9 +198,32 @@ impl From<Message> for RootMessage {
 	}
 }
 
-pub fn make_pattern(input: &State) -> Result<Pattern, LayoutParseError> {
-	Ok(Pattern {
-		time: input.time.build()?,
-		freq: input.freq.build()?,
-	})
+// beats over which the marker morphs from the previous one
+pub fn parse_morph(input: &State) -> Result<f32, LayoutParseError> {
+	match parse_field::<f32>("morph", &input.morph)? {
+		beats if beats >= 0.0 && beats.is_finite() => Ok(beats),
+		_ => Err(LayoutParseError::field("morph", "must be a positive number of beats, or 0")),
+	}
+}
+
+// interval from the base of the previous marker, if the typed base isn't used
+pub fn parse_transpose(input: &State) -> Result<Option<Interval>, LayoutParseError> {
+	match input.transpose.trim() {
+		"" => Ok(None),
+		text => match text.parse::<Interval>() {
+			Ok(interval) if interval.to_f32() > 0.0 && interval.to_f32().is_finite() => Ok(Some(interval)),
+			_ => Err(LayoutParseError::field(
+				"transposition",
+				format!("{} is not an interval", text),
+			)),
+		},
+	}
+}
+
+pub fn make_input(input: &State) -> PatternInput {
+	PatternInput {
+		time: input.time.clone(),
+		freq: input.freq.clone(),
+		kbm: input.kbm.clone(),
+	}
 }
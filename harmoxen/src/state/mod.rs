@@ -1,3 +1,4 @@
+use crate::data::{sheet::SheetError, tempo::TempoChange, track::Target, TempoMap};
 use crate::{backend, widget, Theme};
 use iced::{text_input, Command};
 use std::path::PathBuf;
@@ -31,7 +32,7 @@ pub struct State {
 	pub layout_editor: layout_editor::State,
 	pub settings_editor: settings_editor::State,
 	pub current_editor: CurrentEditor,
-	pub tempo: f32,
+	pub tempo: TempoMap,
 	pub history: History,
 	pub save_path: Option<PathBuf>,
 	pub up_to_date: bool,
@@ -42,9 +43,9 @@ pub struct State {
 impl State {
 	pub fn new(to_server: Sender<crate::Event>) -> State {
 		let sheet_editor = sheet_editor::State::default();
-		let tempo = 120.0;
-		let project = Project::from_state(&sheet_editor, tempo);
-		State {
+		let tempo = TempoMap::default();
+		let project = Project::from_state(&sheet_editor, &tempo);
+		let state = State {
 			wstates: Default::default(),
 			sheet_editor: sheet_editor::State::default(),
 			layout_editor: layout_editor::State::default(),
@@ -56,21 +57,40 @@ impl State {
 			up_to_date: true,
 			theme: Theme::default(),
 			to_server,
-		}
+		};
+		state.send_tracks();
+		state
+	}
+
+	pub fn send_tracks(&self) {
+		let tracks = self.sheet_editor.tracks.clone();
+		let evt = backend::Event::TracksChanged(tracks, self.sheet_editor.curr_track);
+		self.to_server.send(crate::Event::ToBackend(evt)).ok();
+	}
+
+	// keeps the backends in time while playing
+	pub fn send_tempo(&self) {
+		let evt = backend::Event::SetTempo(self.tempo.clone());
+		self.to_server.send(crate::Event::ToBackend(evt)).ok();
 	}
 
 	pub fn apply_layout(&mut self) -> Result<(), layout_editor::LayoutParseError> {
 		let curr_marker = self.sheet_editor.curr_marker;
 		let layout = &mut self.sheet_editor.layout;
-		let pattern = layout_editor::make_pattern(&self.layout_editor)?;
-		layout.set_marker_pattern(curr_marker, pattern);
+		let input = layout_editor::make_input(&self.layout_editor);
+		let morph = layout_editor::parse_morph(&self.layout_editor)?;
+		let transpose = layout_editor::parse_transpose(&self.layout_editor)?;
+		layout.set_marker_pattern(curr_marker, input)?;
+		layout.markers[curr_marker].morph = morph;
+		layout.markers[curr_marker].transpose = transpose;
+		self.sheet_editor.sync_layout();
 		Ok(())
 	}
 }
 
 pub struct UpdateCtx<'a> {
 	to_server: &'a mut Sender<crate::Event>,
-	tempo: f32,
+	tempo: &'a TempoMap,
 	project_changed: &'a mut bool,
 }
 impl<'a> UpdateCtx<'a> {
@@ -89,25 +109,34 @@ impl State {
 			Message::SheetEditor(msg) => {
 				let ctx = UpdateCtx {
 					to_server: &mut self.to_server,
-					tempo: self.tempo,
+					tempo: &self.tempo,
 					project_changed: &mut project_changed,
 				};
 				self.sheet_editor.update(msg, ctx);
+				// a successful edit makes the last error stale
+				if project_changed {
+					self.sheet_editor.error = None;
+					self.sheet_editor.layout_error = None;
+				}
 			}
 			Message::LayoutEditor(msg) => {
 				self.layout_editor.update(msg);
 			}
 			Message::ProjectNew => {
 				self.sheet_editor = sheet_editor::State::default();
+				self.sheet_editor
+					.set_default_target(Target::of_backend(&self.settings_editor.backend_id));
+				self.send_tracks();
 			}
 			Message::ProjectOpen => {
 				if let Some(path) = rfd::FileDialog::new().add_filter("hxp", &["hxp"]).pick_file() {
 					println!("open location: {:?}", path);
-					if let Ok(project_str) = std::fs::read_to_string(path) {
-						let project = ron::from_str::<Project>(&project_str);
-						if let Ok(project) = project {
-							project.open(self);
-						}
+					let project = std::fs::read_to_string(path)
+						.map_err(|e| SheetError::Unreadable(e.to_string()))
+						.and_then(|project_str| Project::parse(&project_str));
+					match project {
+						Ok(project) => project.open(self),
+						Err(e) => self.sheet_editor.error = Some(e),
 					}
 				}
 			}
@@ -128,6 +157,14 @@ impl State {
 				self.current_editor = CurrentEditor::SettingsEditor;
 			}
 			Message::OpenLayout => {
+				let marker = &self.sheet_editor.layout.markers[self.sheet_editor.curr_marker];
+				let input = &marker.pattern_input;
+				self.layout_editor.time = input.time.clone();
+				self.layout_editor.freq = input.freq.clone();
+				self.layout_editor.kbm = input.kbm.clone();
+				self.layout_editor.morph = marker.morph.to_string();
+				self.layout_editor.transpose = marker.transpose.map_or(String::new(), |x| x.to_string());
+				self.layout_editor.error = None;
 				self.current_editor = CurrentEditor::LayoutEditor;
 			}
 			Message::Backend(evt) => {
@@ -135,13 +172,31 @@ impl State {
 			}
 			Message::ChangeBackend(backend) => {
 				self.settings_editor.backend_id = backend.clone();
+				self.sheet_editor.set_default_target(Target::of_backend(&backend));
 				self.to_server.send(crate::Event::ChangeBackend(backend)).unwrap();
+				self.send_tracks();
 			}
 			Message::ApplyLayout => {
-				self.apply_layout().ok();
+				self.layout_editor.error = self.apply_layout().err();
 			}
 			Message::SetTempo(tempo) => {
-				self.tempo = tempo;
+				self.tempo.initial = tempo;
+				self.send_tempo();
+				project_changed = true
+			}
+			Message::TempoChangeAdd(change) => {
+				self.tempo.add_change(change);
+				self.send_tempo();
+				project_changed = true
+			}
+			Message::TempoChangeSet(idx, change) => {
+				self.tempo.set_change(idx, change);
+				self.send_tempo();
+				project_changed = true
+			}
+			Message::TempoChangeDelete(idx) => {
+				self.tempo.remove_change(idx);
+				self.send_tempo();
 				project_changed = true
 			}
 			Message::Undo => {
@@ -159,7 +214,7 @@ impl State {
 		};
 
 		if project_changed {
-			let project = Project::from_state(&self.sheet_editor, self.tempo);
+			let project = Project::from_state(&self.sheet_editor, &self.tempo);
 			self.history.save(project);
 		}
 
@@ -174,7 +229,7 @@ impl State {
 	where
 		P: AsRef<std::path::Path>,
 	{
-		let project = Project::from_state(&self.sheet_editor, self.tempo);
+		let project = Project::from_state(&self.sheet_editor, &self.tempo);
 		let project_str = ron::to_string(&project).unwrap();
 		std::fs::write(path, project_str).ok();
 	}
@@ -197,4 +252,7 @@ pub enum Message {
 	Backend(crate::backend::Event),
 	ChangeBackend(crate::BackendId),
 	SetTempo(f32),
+	TempoChangeAdd(TempoChange),
+	TempoChangeSet(usize, TempoChange),
+	TempoChangeDelete(usize),
 }
@@ -1,7 +1,12 @@
 use serde::{Deserialize, Serialize};
 
 use crate::data::layout::Layout;
-use crate::data::sheet::Sheet;
+use crate::data::{
+	sheet::{Sheet, SheetError},
+	tempo::TempoChange,
+	track::Target,
+	Playback, TempoMap, Track,
+};
 use crate::state::{sheet_editor, State};
 use generational_arena::Index;
 use std::collections::HashSet;
@@ -12,26 +17,73 @@ pub struct Project {
 	pub layout: Layout,
 	pub selection: HashSet<Index>,
 	pub tempo: f32,
+	#[serde(default)]
+	pub tempo_changes: Vec<TempoChange>,
+	#[serde(default)]
+	pub tracks: Vec<Track>,
+	#[serde(default = "follow_backend")]
+	pub tracks_follow_backend: bool,
+	#[serde(default)]
+	pub playback: Playback,
+}
+
+// projects saved before targets could be set by hand play on the selected backend
+fn follow_backend() -> bool {
+	true
 }
 
 impl Project {
-	pub fn from_state(sheet_editor: &sheet_editor::State, tempo: f32) -> Project {
+	pub fn from_state(sheet_editor: &sheet_editor::State, tempo: &TempoMap) -> Project {
 		let layout = sheet_editor.layout.clone();
 		let sheet = sheet_editor.sheet.clone();
 		let selection = sheet_editor.selection.clone();
-		let tempo = tempo;
+		let tempo_changes = tempo.changes.clone();
+		let tempo = tempo.initial;
+		let tracks = sheet_editor.tracks.clone();
+		let tracks_follow_backend = sheet_editor.tracks_follow_backend;
+		let playback = sheet_editor.playback;
 		Project {
 			sheet,
 			layout,
 			selection,
 			tempo,
+			tempo_changes,
+			tracks,
+			tracks_follow_backend,
+			playback,
 		}
 	}
 
+	// reads a saved project, rejecting it if its sheet is broken
+	pub fn parse(text: &str) -> Result<Project, SheetError> {
+		let project = ron::from_str::<Project>(text).map_err(|e| SheetError::Unreadable(e.to_string()))?;
+		project.sheet.validate()?;
+		Ok(project)
+	}
+
 	pub fn open(self, state: &mut State) {
 		state.sheet_editor.layout = self.layout;
 		state.sheet_editor.sheet = self.sheet;
+		state.sheet_editor.sync_layout();
 		state.sheet_editor.selection = self.selection;
-		state.tempo = self.tempo;
+		state.sheet_editor.error = None;
+		state.sheet_editor.playback = self.playback;
+		state.tempo = TempoMap {
+			initial: self.tempo,
+			changes: self.tempo_changes,
+		};
+		// projects saved before tracks existed get a default one, on the selected backend
+		state.sheet_editor.tracks_follow_backend = self.tracks_follow_backend;
+		state.sheet_editor.tracks = if self.tracks.is_empty() {
+			vec![Track::default()]
+		} else {
+			self.tracks
+		};
+		state
+			.sheet_editor
+			.set_default_target(Target::of_backend(&state.settings_editor.backend_id));
+		state.sheet_editor.sync_tracks();
+		state.send_tracks();
+		state.send_tempo();
 	}
 }
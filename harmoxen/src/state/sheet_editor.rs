@@ -1,15 +1,18 @@
 use super::UpdateCtx;
 use crate::backend;
+use crate::consts::RATIO_TOLERANCE;
 use crate::data::{
 	self,
-	layout::Layout,
-	sheet::{self, Clipboard, Pitch, Sheet},
-	Frame, Frame2, Point, Range,
+	layout::{freq_input::FreqInput, scala, tun, Layout, LayoutParseError, PatternInput},
+	sheet::{self, Clipboard, Interval, Limit, Pitch, Sheet, SheetError, Transform},
+	track::Target,
+	Frame, Frame2, Playback, Point, Range, Track,
 };
 use crate::state::Message as RootMessage;
 use crate::widget;
+use crate::widget::sheet_editor::interval_input::IntervalTarget;
 use generational_arena::Index;
-use iced::Command;
+use iced::{button, pick_list, text_input, Command};
 use std::collections::HashSet;
 use std::time::Instant;
 
@@ -24,6 +27,19 @@ pub struct WStates {
 	pub preview: widget::sheet_editor::preview::State,
 	pub interval_input: Option<widget::sheet_editor::interval_input::State>,
 	pub selection: widget::sheet_editor::selection::State,
+	pub arrangement: widget::sheet_editor::arrangement::State,
+	pub loop_editor: widget::sheet_editor::loop_editor::State,
+	pub tempo_editor: widget::sheet_editor::tempo_editor::State,
+	pub tracks: Vec<TrackWStates>,
+	pub add_track: button::State,
+	pub snap_limit: pick_list::State<Limit>,
+	pub velocity: widget::parse::State<widget::text_input::State, String>,
+}
+
+#[derive(Default)]
+pub struct TrackWStates {
+	pub name: text_input::State,
+	pub target: pick_list::State<Target>,
 }
 
 pub enum PlayingState {
@@ -52,6 +68,20 @@ pub struct State {
 	pub curr_marker: usize,
 	pub selection: HashSet<Index>,
 	pub clipboard: Clipboard,
+	pub error: Option<SheetError>,
+	// from layouts made out of the sheet, e.g. a scale from the selection
+	pub layout_error: Option<LayoutParseError>,
+	pub tracks: Vec<Track>,
+	pub curr_track: usize,
+	// tracks whose notes can't be seen or picked on the board
+	pub hidden_tracks: HashSet<usize>,
+	// target of the selected backend, given to new tracks
+	pub default_target: Target,
+	// whether no target was set by hand yet, so that the tracks follow the selected backend
+	pub tracks_follow_backend: bool,
+	pub harmonic_snap: bool,
+	pub snap_limit: Limit,
+	pub playback: Playback,
 }
 impl Default for State {
 	fn default() -> State {
@@ -78,6 +108,16 @@ impl Default for State {
 			curr_marker: 0,
 			selection: HashSet::new(),
 			clipboard: Clipboard::new(),
+			error: None,
+			layout_error: None,
+			tracks: vec![Track::default()],
+			curr_track: 0,
+			hidden_tracks: HashSet::new(),
+			default_target: Target::Audio,
+			tracks_follow_backend: true,
+			harmonic_snap: false,
+			snap_limit: Limit::Odd(7),
+			playback: Playback::default(),
 		}
 	}
 }
@@ -107,26 +147,50 @@ impl State {
 					self.cursor = origin;
 					self.playing_state = PlayingState::Stopped;
 				} else {
-					ctx.to_backend(backend::Event::SetTempo(ctx.tempo));
-					ctx.to_backend(backend::Event::PlayStart(self.sheet.clone(), self.cursor));
+					ctx.to_backend(backend::Event::SetTempo(ctx.tempo.clone()));
+					ctx.to_backend(backend::Event::PlaybackChanged(self.playback));
+					ctx.to_backend(backend::Event::PlayStart(
+						self.sheet.clone(),
+						self.tracks.clone(),
+						self.cursor,
+					));
 					self.playing_state = PlayingState::Playing(self.cursor);
 					self.last_tick = Instant::now();
 				}
 			}
 			Message::CursorTick(now) => {
-				self.cursor += now.duration_since(self.last_tick).as_secs_f32() * (ctx.tempo / 60.0);
-				let wrap_size = self.sheet.get_size().ceil();
-				self.cursor %= wrap_size;
+				let seconds = now.duration_since(self.last_tick).as_secs_f32();
+				let length = ctx.tempo.advance(self.cursor, seconds) - self.cursor;
+				match self.playback.advance(self.cursor, length, &self.sheet) {
+					Some(cursor) => self.cursor = cursor,
+					None => {
+						// the backends stopped by themselves at the end of the sheet
+						if let PlayingState::Playing(origin) = self.playing_state {
+							self.cursor = origin;
+						}
+						self.playing_state = PlayingState::Stopped;
+					}
+				}
 				self.last_tick = now;
 			}
 			Message::SetCursor(at) => {
 				self.cursor = at;
 			}
-			Message::NoteAdd(note, mov) => {
-				let idx = self.sheet.add_note(note);
+			Message::NoteAdd(mut note, mov) => {
+				if let Err(e) = self.sheet.check_pitch(None, note.pitch) {
+					self.error = Some(e);
+					return Command::none();
+				}
+				note.track = self.curr_track;
+				let idx = self.sheet.add_note(note.clone());
+				let instances = self.sheet.add_to_instance(idx).into_iter().collect();
+				self.sync_instances(instances);
 
 				if let Pitch::Relative(_, _) = note.pitch {
-					self.wstates.interval_input = Some(widget::sheet_editor::interval_input::State::new(&self.sheet, idx));
+					self.wstates.interval_input = Some(widget::sheet_editor::interval_input::State::new(
+						&self.sheet,
+						IntervalTarget::Note(idx),
+					));
 				} else if mov {
 					let rect = note.rect(&self.sheet, 0.0);
 					self.wstates.board.set_action_move(idx, rect);
@@ -135,25 +199,37 @@ impl State {
 			}
 			Message::NoteMove(idx, pos) => {
 				self.sheet.move_note(idx, pos.x, pos.y);
+				self.sync_notes(&[idx]);
 				ctx.project_changed();
 			}
 			Message::NoteResize(idx, len) => {
-				let note = self.sheet.get_note_mut(idx).expect("tried to resize dead note");
-				note.length = len;
+				let note = self.sheet.get_note(idx).expect("tried to resize dead note");
+				self.sheet.resize_note_to(idx, note.start + len);
+				self.sync_notes(&[idx]);
 				ctx.project_changed();
 			}
 			Message::NoteDelete(idx) => {
+				let instances = self.instances_of(&[idx]);
 				self.sheet.remove_note(idx);
+				self.sync_instances(instances);
 				self.wstates.interval_input = None;
 				ctx.project_changed();
 			}
-			Message::NoteSetPitch(idx, pitch) => {
-				let note = self.sheet.get_note_mut(idx).expect("tried to change pitch of dead note");
-				note.pitch = pitch;
+			Message::NoteSetPitch(idx, pitch) => match self.sheet.set_pitch(idx, pitch) {
+				Ok(()) => {
+					self.sync_notes(&[idx]);
+					self.error = None;
+					ctx.project_changed();
+				}
+				Err(e) => self.error = Some(e),
+			},
+			Message::NoteSetBend(idx, bend) => {
+				self.sheet.set_bend(idx, bend);
+				self.sync_notes(&[idx]);
 				ctx.project_changed();
 			}
-			Message::OpenIntervalInput(idx) => {
-				self.wstates.interval_input = Some(widget::sheet_editor::interval_input::State::new(&self.sheet, idx));
+			Message::OpenIntervalInput(target) => {
+				self.wstates.interval_input = Some(widget::sheet_editor::interval_input::State::new(&self.sheet, target));
 			}
 			Message::CloseIntervalInput => {
 				self.wstates.interval_input = None;
@@ -161,8 +237,12 @@ impl State {
 			Message::AddMarker(at) => {
 				let mut new_marker = self.layout.markers[self.curr_marker].clone();
 				new_marker.at = at;
+				// keeps its own base, transposing from the previous marker is opt-in
+				new_marker.anchor = None;
+				new_marker.transpose = None;
 				let idx = self.layout.add_marker(new_marker);
 				self.curr_marker = idx;
+				self.sync_layout();
 				ctx.project_changed();
 			}
 			Message::SelectMarker(idx) => {
@@ -171,14 +251,107 @@ impl State {
 			}
 			Message::MoveMarker(at) => {
 				self.curr_marker = self.layout.set_marker_time(self.curr_marker, at);
+				self.sync_layout();
 				ctx.project_changed();
 			}
 			Message::DeleteMarker(idx) => {
 				self.layout.delete_marker(idx);
+				self.sync_layout();
 				ctx.project_changed();
 			}
+			Message::ScaleFromSelection(base, cents, period) => {
+				if !(period.to_f32() > 1.0 && period.to_f32().is_finite()) {
+					self.layout_error = Some(LayoutParseError::field("period", "must be greater than 1"));
+					return Command::none();
+				}
+				let notes = self.selection.iter().copied().collect::<Vec<_>>();
+				let sheet = &self.sheet;
+				let freq = |idx: &Index| sheet.get_freq(sheet.notes[*idx].pitch, sheet.notes[*idx].start);
+				let base = match base.or_else(|| notes.iter().copied().min_by(|a, b| freq(a).partial_cmp(&freq(b)).unwrap())) {
+					Some(base) => base,
+					None => return Command::none(),
+				};
+				// the notes are folded into the typed period
+				let at = self.cursor;
+				let values = sheet.get_scale(&notes, base, period, RATIO_TOLERANCE, cents);
+				let input = PatternInput {
+					time: self.layout.get_marker_at(at, None).pattern_input.time.clone(),
+					freq: FreqInput::Intervals {
+						base: freq(&base).to_string(),
+						values: values.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(" "),
+					},
+					kbm: None,
+				};
+				let pattern = match input.build() {
+					Ok(pattern) => pattern,
+					Err(e) => {
+						self.layout_error = Some(e);
+						return Command::none();
+					}
+				};
+				let mut marker = self.layout.get_marker_at(at, None).clone();
+				marker.at = at;
+				marker.anchor = None;
+				marker.morph = 0.0;
+				marker.transpose = None;
+				marker.pattern = pattern;
+				marker.pattern_input = input;
+				let idx = self.layout.add_marker(marker);
+				self.curr_marker = idx;
+				self.sync_layout();
+				ctx.project_changed();
+			}
+			Message::SetMarkerAnchor(idx, anchor) => {
+				if let Some(root) = anchor.and_then(|note| self.layout.degree_root(&self.sheet, idx, note)) {
+					// the note leaves the marker's degrees, so that the base can follow it
+					let note = self.sheet.get_note(root).unwrap();
+					let freq = self.sheet.get_freq(note.pitch, note.start);
+					if self.sheet.set_pitch(root, Pitch::Absolute(freq)).is_ok() {
+						self.sync_notes(&[root]);
+					}
+				}
+				if anchor.is_none() {
+					// the typed base is back in use
+					let input = self.layout.markers[idx].pattern_input.clone();
+					if let Err(e) = self.layout.set_marker_pattern(idx, input) {
+						self.layout_error = Some(e);
+						return Command::none();
+					}
+				}
+				self.layout.markers[idx].anchor = anchor;
+				self.sync_layout();
+				ctx.project_changed();
+			}
+			Message::ExportScala(idx) => {
+				if let Some(path) = rfd::FileDialog::new().add_filter("scl", &["scl"]).save_file() {
+					let name = path.file_stem().map_or(String::new(), |x| x.to_string_lossy().into_owned());
+					if let Some((scl, kbm)) = scala::export(&self.layout.markers[idx].pattern, &name) {
+						// the mapping goes next to the scale, under the same name
+						let kbm_path = path.with_extension("kbm");
+						if let Err(e) = std::fs::write(&path, scl.to_string()).and(std::fs::write(&kbm_path, kbm.to_string())) {
+							println!("could not export {:?}: {}", path, e);
+						}
+					}
+				}
+			}
+			Message::ExportTun(idx) => {
+				if let Some(path) = rfd::FileDialog::new().add_filter("tun", &["tun"]).save_file() {
+					let name = path.file_stem().map_or(String::new(), |x| x.to_string_lossy().into_owned());
+					if let Some(tun) = tun::export(&self.layout.markers[idx].pattern, &name) {
+						if let Err(e) = std::fs::write(&path, tun) {
+							println!("could not export {:?}: {}", path, e);
+						}
+					}
+				}
+			}
 			Message::SelectAll => {
-				self.selection = self.sheet.indices.iter().copied().collect();
+				let (sheet, hidden) = (&self.sheet, &self.hidden_tracks);
+				self.selection = sheet
+					.indices
+					.iter()
+					.copied()
+					.filter(|&idx| !hidden.contains(&sheet.notes[idx].track))
+					.collect();
 				ctx.project_changed();
 			}
 			Message::SetSelection(selection) => {
@@ -186,25 +359,196 @@ impl State {
 				ctx.project_changed();
 			}
 			Message::Cut => {
+				let instances = self.instances_of(&self.selection.iter().copied().collect::<Vec<_>>());
 				self.clipboard.cut(&mut self.sheet, &mut self.selection);
+				self.sync_instances(instances);
 				ctx.project_changed();
 			}
 			Message::Copy => {
 				self.clipboard.copy(&mut self.sheet, &mut self.selection);
 			}
-			Message::Paste => {
-				self.clipboard.paste(&mut self.sheet, &mut self.selection);
+			Message::Paste => match self.clipboard.paste(&mut self.sheet, &mut self.selection) {
+				Ok(()) => {
+					let sheet = &mut self.sheet;
+					let instances = self.selection.iter().filter_map(|&idx| sheet.add_to_instance(idx)).collect();
+					self.sync_instances(instances);
+					self.error = None;
+					ctx.project_changed();
+				}
+				Err(e) => self.error = Some(e),
+			},
+			Message::Transform(transform) => {
+				let notes = self.sheet.transform(&self.selection, transform, &self.hidden_tracks);
+				self.sync_notes(&notes);
+				ctx.project_changed();
+			}
+			Message::SetLoop(region) => {
+				self.playback.region = region;
+				ctx.to_backend(backend::Event::PlaybackChanged(self.playback));
+				ctx.project_changed();
+			}
+			Message::ToggleLooping => {
+				self.playback.looping = !self.playback.looping;
+				ctx.to_backend(backend::Event::PlaybackChanged(self.playback));
+				ctx.project_changed();
+			}
+			Message::SnapToggle => {
+				self.harmonic_snap = !self.harmonic_snap;
+			}
+			Message::SetVelocity(velocity) => {
+				let notes = self.selection.iter().copied().collect::<Vec<_>>();
+				for &idx in &notes {
+					self.sheet.set_velocity(idx, velocity);
+				}
+				self.sync_notes(&notes);
 				ctx.project_changed();
 			}
+			Message::SnapSetLimit(limit) => {
+				self.snap_limit = limit;
+				self.harmonic_snap = true;
+			}
+			Message::TrackAdd => {
+				let mut track = Track::new(format!("Track {}", self.tracks.len() + 1));
+				track.target = self.default_target;
+				self.tracks.push(track);
+				self.curr_track = self.tracks.len() - 1;
+				self.tracks_changed(&mut ctx);
+			}
+			Message::TrackSelect(idx) => {
+				self.curr_track = idx;
+				ctx.to_backend(backend::Event::TracksChanged(self.tracks.clone(), self.curr_track));
+			}
+			Message::TrackRename(idx, name) => {
+				self.tracks[idx].name = name;
+				ctx.project_changed();
+			}
+			Message::TrackMute(idx) => {
+				self.tracks[idx].mute = !self.tracks[idx].mute;
+				self.tracks_changed(&mut ctx);
+			}
+			Message::TrackSolo(idx) => {
+				self.tracks[idx].solo = !self.tracks[idx].solo;
+				self.tracks_changed(&mut ctx);
+			}
+			Message::TrackShow(idx) => {
+				self.tracks[idx].visible = !self.tracks[idx].visible;
+				self.tracks_changed(&mut ctx);
+			}
+			Message::TrackSetTarget(idx, target) => {
+				self.tracks[idx].target = target;
+				self.tracks_follow_backend = false;
+				self.tracks_changed(&mut ctx);
+			}
 			Message::Delete => {
+				let instances = self.instances_of(&self.selection.iter().copied().collect::<Vec<_>>());
 				for idx in self.selection.drain() {
 					self.sheet.remove_note(idx);
 				}
+				self.sync_instances(instances);
+				ctx.project_changed();
+			}
+			Message::ClipCreate => {
+				let notes: Vec<Index> = self.selection.iter().copied().collect();
+				let name = format!("Clip {}", self.sheet.clips.len() + 1);
+				self.sheet.make_clip(name, &notes);
+				ctx.project_changed();
+			}
+			Message::ClipInstance(clip, at) => {
+				let idx = self.sheet.add_instance(clip, at, Interval::UNISON);
+				self.selection = self.sheet.instances[idx].notes.iter().copied().collect();
+				ctx.project_changed();
+			}
+			Message::InstanceSelect(idx) => {
+				self.selection = self.sheet.instances[idx].notes.iter().copied().collect();
+			}
+			Message::InstanceMove(idx, offset) => {
+				self.sheet.move_instance(idx, offset);
+				self.selection = self.sheet.instances[idx].notes.iter().copied().collect();
+				ctx.project_changed();
+			}
+			Message::InstanceTranspose(idx, interval) => {
+				self.sheet.transpose_instance(idx, interval);
+				self.selection = self.sheet.instances[idx].notes.iter().copied().collect();
+				ctx.project_changed();
+			}
+			Message::InstanceMakeUnique(idx) => {
+				self.sheet.make_unique(idx);
+				ctx.project_changed();
+			}
+			Message::InstanceDelete(idx) => {
+				self.sheet.remove_instance(idx);
+				let sheet = &self.sheet;
+				self.selection.retain(|&idx| sheet.notes.contains(idx));
+				// the following instances moved down
+				if let Some(wstate) = &self.wstates.interval_input {
+					if !wstate.is_valid(sheet) || matches!(wstate.target(), IntervalTarget::Instance(_)) {
+						self.wstates.interval_input = None;
+					}
+				}
 				ctx.project_changed();
 			}
 		}
+		// anchored markers follow their notes, and transposed ones the markers before them
+		if self.layout.resolve_bases(&self.sheet) {
+			self.sheet.set_layout(&self.layout);
+		}
 		Command::none()
 	}
+
+	// gives the layout to the sheet, after moving the bases of anchored and transposed markers
+	pub fn sync_layout(&mut self) {
+		self.layout.resolve_bases(&self.sheet);
+		self.sheet.set_layout(&self.layout);
+	}
+
+	fn instances_of(&self, notes: &[Index]) -> HashSet<usize> {
+		notes.iter().filter_map(|&idx| self.sheet.get_instance_of(idx)).collect()
+	}
+
+	// propagates edits of notes to the other instances of their clips
+	fn sync_notes(&mut self, notes: &[Index]) {
+		let instances = self.instances_of(notes);
+		self.sync_instances(instances);
+	}
+
+	fn sync_instances(&mut self, instances: HashSet<usize>) {
+		if instances.is_empty() {
+			return;
+		}
+		for instance in instances {
+			self.sheet.sync_instance(instance);
+		}
+		// notes removed from a clip are gone from its other instances
+		let sheet = &self.sheet;
+		self.selection.retain(|&idx| sheet.notes.contains(idx));
+		if let Some(wstate) = &self.wstates.interval_input {
+			if !wstate.is_valid(sheet) {
+				self.wstates.interval_input = None;
+			}
+		}
+	}
+
+	fn tracks_changed(&mut self, ctx: &mut UpdateCtx) {
+		self.sync_tracks();
+		ctx.to_backend(backend::Event::TracksChanged(self.tracks.clone(), self.curr_track));
+		ctx.project_changed();
+	}
+
+	// to be called when the backend changes, or when the tracks are replaced
+	pub fn set_default_target(&mut self, target: Target) {
+		self.default_target = target;
+		if self.tracks_follow_backend {
+			for track in &mut self.tracks {
+				track.target = target;
+			}
+		}
+	}
+
+	// applies the visibility of tracks to the board, after they were changed or loaded
+	pub fn sync_tracks(&mut self) {
+		self.curr_track = self.curr_track.min(self.tracks.len() - 1);
+		self.hidden_tracks = (0..self.tracks.len()).filter(|&i| !self.tracks[i].visible).collect();
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -217,23 +561,48 @@ pub enum Message {
 	Play,
 	CursorTick(Instant),
 	SetCursor(f32),
+	SetLoop(Option<Range>),
+	ToggleLooping,
 	NoteAdd(sheet::Note, bool), // if true: initiate move action
 	NoteMove(sheet::Index, Point),
 	NoteResize(sheet::Index, f32),
 	NoteDelete(sheet::Index),
 	NoteSetPitch(sheet::Index, Pitch),
-	OpenIntervalInput(sheet::Index),
+	NoteSetBend(sheet::Index, Vec<sheet::BendPoint>),
+	OpenIntervalInput(IntervalTarget),
 	CloseIntervalInput,
 	AddMarker(f32),
 	SelectMarker(usize),
 	MoveMarker(f32),
 	DeleteMarker(usize),
+	SetMarkerAnchor(usize, Option<Index>),
+	ScaleFromSelection(Option<Index>, bool, Interval), // base, lowest note if None, whether the scale is in cents, and period
+	ExportScala(usize),
+	ExportTun(usize),
 	SelectAll,
 	SetSelection(HashSet<Index>),
 	Cut,
 	Copy,
 	Paste,
 	Delete,
+	Transform(Transform),
+	SnapToggle,
+	SnapSetLimit(Limit),
+	SetVelocity(f32),
+	TrackAdd,
+	TrackSelect(usize),
+	TrackRename(usize, String),
+	TrackMute(usize),
+	TrackSolo(usize),
+	TrackShow(usize),
+	TrackSetTarget(usize, Target),
+	ClipCreate,
+	ClipInstance(usize, f32),
+	InstanceSelect(usize),
+	InstanceMove(usize, f32),
+	InstanceTranspose(usize, Interval),
+	InstanceMakeUnique(usize),
+	InstanceDelete(usize),
 }
 
 impl From<Message> for RootMessage {
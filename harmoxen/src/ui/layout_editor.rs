@@ -3,12 +3,16 @@ use crate::state::{layout_editor::Message, Message as RootMessage};
 use crate::{
 	data::layout::{
 		freq_input::{self, FreqInput},
+		scala::{Kbm, Scl},
 		time_input::{self, TimeInput},
 	},
 	Theme,
 };
 use iced::{text_input, Button, Column, Container, Element, Length, PickList, Row, Text, TextInput};
 
+// largest scale size checked for moments of symmetry
+const MOS_MAX_SIZE: usize = 60;
+
 fn textbox<'a, F>(
 	state: &'a mut text_input::State,
 	placeholder: &'static str,
@@ -123,6 +127,13 @@ pub fn build<'a>(state: &mut State, theme: Theme) -> Element<RootMessage> {
 							freq_input::Mode::Equal,
 							freq_input::Mode::Enumeration,
 							freq_input::Mode::HarmonicSegment,
+							freq_input::Mode::Scala,
+							freq_input::Mode::Generated,
+							freq_input::Mode::SubharmonicSegment,
+							freq_input::Mode::Cps,
+							freq_input::Mode::Diamond,
+							freq_input::Mode::Formula,
+							freq_input::Mode::Intervals,
 						][..],
 						Some(state.freq.mode()),
 						|mode| Message::SetFreqMode(mode).into(),
@@ -131,7 +142,8 @@ pub fn build<'a>(state: &mut State, theme: Theme) -> Element<RootMessage> {
 					.width(Length::Units(175)),
 				)
 				.push({
-					let [state0, state1, state2] = &mut state.wstates_freq;
+					let [state0, state1, state2, state3, state4] = &mut state.wstates_freq;
+					let load_btn_state = &mut state.load_btn_state;
 					match &state.freq {
 						FreqInput::None => Into::<Element<RootMessage>>::into(Text::new("The frequency axis will be free")),
 						FreqInput::Equal { base, interval, ndiv } => Row::new()
@@ -196,9 +208,271 @@ pub fn build<'a>(state: &mut State, theme: Theme) -> Element<RootMessage> {
 								theme,
 							))
 							.into(),
+						FreqInput::Scala { base, scl } => Row::new()
+							.push(textbox(
+								state0,
+								"base frequency",
+								&base,
+								|text| Message::SetFreqField(0, text).into(),
+								theme,
+							))
+							.push(
+								Container::new(Text::new(match Scl::parse(&scl) {
+									Ok(scl) => format!("{} ({} notes)", scl.description, scl.degrees.len()),
+									Err(_) => String::from("Invalid .scl file"),
+								}))
+								.width(Length::FillPortion(1))
+								.padding(5),
+							)
+							.push(
+								Button::new(load_btn_state, Text::new("Load .scl"))
+									.on_press(Message::LoadScl.into())
+									.style(theme),
+							)
+							.into(),
+						FreqInput::Generated {
+							base,
+							period,
+							generator,
+							size,
+							down,
+						} => Column::new()
+							.push(
+								Row::new()
+									.push(textbox(
+										state0,
+										"base frequency",
+										&base,
+										|text| Message::SetFreqField(0, text).into(),
+										theme,
+									))
+									.push(textbox(
+										state1,
+										"period",
+										&period,
+										|text| Message::SetFreqField(1, text).into(),
+										theme,
+									))
+									.push(textbox(
+										state2,
+										"generator",
+										&generator,
+										|text| Message::SetFreqField(2, text).into(),
+										theme,
+									))
+									.push(textbox(
+										state3,
+										"# notes",
+										&size,
+										|text| Message::SetFreqField(3, text).into(),
+										theme,
+									))
+									.push(textbox(
+										state4,
+										"# generators down",
+										&down,
+										|text| Message::SetFreqField(4, text).into(),
+										theme,
+									)),
+							)
+							.push(
+								Container::new(Text::new(match freq_input::parse_generator(&period, &generator) {
+									Ok((period, generator)) => format!(
+										"MOS sizes: {}",
+										freq_input::mos_sizes(period, generator, MOS_MAX_SIZE)
+											.iter()
+											.map(|x| x.to_string())
+											.collect::<Vec<String>>()
+											.join(", ")
+									),
+									Err(_) => String::from("Invalid period or generator"),
+								}))
+								.padding(5),
+							)
+							.into(),
+						FreqInput::SubharmonicSegment { base, from, to } => Row::new()
+							.push(textbox(
+								state0,
+								"base frequency",
+								&base,
+								|text| Message::SetFreqField(0, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state1,
+								"from",
+								&from,
+								|text| Message::SetFreqField(1, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state2,
+								"to",
+								&to,
+								|text| Message::SetFreqField(2, text).into(),
+								theme,
+							))
+							.into(),
+						FreqInput::Cps {
+							base,
+							factors,
+							choose,
+							period,
+						} => Row::new()
+							.push(textbox(
+								state0,
+								"base frequency",
+								&base,
+								|text| Message::SetFreqField(0, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state1,
+								"factors",
+								&factors,
+								|text| Message::SetFreqField(1, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state2,
+								"# factors per product",
+								&choose,
+								|text| Message::SetFreqField(2, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state3,
+								"period",
+								&period,
+								|text| Message::SetFreqField(3, text).into(),
+								theme,
+							))
+							.into(),
+						FreqInput::Diamond { base, limit, period } => Row::new()
+							.push(textbox(
+								state0,
+								"base frequency",
+								&base,
+								|text| Message::SetFreqField(0, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state1,
+								"odd limit",
+								&limit,
+								|text| Message::SetFreqField(1, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state2,
+								"period",
+								&period,
+								|text| Message::SetFreqField(2, text).into(),
+								theme,
+							))
+							.into(),
+						FreqInput::Formula {
+							base,
+							count,
+							period,
+							formula,
+						} => Row::new()
+							.push(textbox(
+								state0,
+								"base frequency",
+								&base,
+								|text| Message::SetFreqField(0, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state1,
+								"# notes",
+								&count,
+								|text| Message::SetFreqField(1, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state2,
+								"period",
+								&period,
+								|text| Message::SetFreqField(2, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state3,
+								"F: i -> ratio",
+								&formula,
+								|text| Message::SetFreqField(3, text).into(),
+								theme,
+							))
+							.into(),
+						FreqInput::Intervals { base, values } => Row::new()
+							.push(textbox(
+								state0,
+								"base frequency",
+								&base,
+								|text| Message::SetFreqField(0, text).into(),
+								theme,
+							))
+							.push(textbox(
+								state1,
+								"intervals, up to the period",
+								&values,
+								|text| Message::SetFreqField(1, text).into(),
+								theme,
+							))
+							.into(),
 					}
 				}),
 		)
+		.push(
+			Row::new()
+				.push(
+					Container::new(Text::new(match state.kbm.as_ref().map(|text| Kbm::parse(text)) {
+						None => String::from("No keyboard mapping, A4 plays the base frequency"),
+						Some(Ok(kbm)) => format!("Keyboard mapping: key {} plays {} Hz", kbm.reference, kbm.freq),
+						Some(Err(_)) => String::from("Invalid .kbm file"),
+					}))
+					.width(Length::Fill)
+					.padding(5),
+				)
+				.push(
+					Button::new(&mut state.load_kbm_btn_state, Text::new("Load .kbm"))
+						.on_press(Message::LoadKbm.into())
+						.style(theme),
+				)
+				.push(
+					Button::new(&mut state.clear_kbm_btn_state, Text::new("Clear"))
+						.on_press(Message::ClearKbm.into())
+						.style(theme),
+				),
+		)
+		.push(
+			Row::new()
+				.push(Container::new(Text::new("Morph from the previous marker over (beats)")).padding(5))
+				.push(textbox(
+					&mut state.morph_input_state,
+					"0 for an abrupt change",
+					&state.morph,
+					|text| Message::SetMorph(text).into(),
+					theme,
+				)),
+		)
+		.push(
+			Row::new()
+				.push(Container::new(Text::new("Transpose from the previous marker by")).padding(5))
+				.push(textbox(
+					&mut state.transpose_input_state,
+					"interval, empty to use the base frequency",
+					&state.transpose,
+					|text| Message::SetTranspose(text).into(),
+					theme,
+				)),
+		)
+		.push(Text::new(match &state.error {
+			Some(e) => format!("Invalid layout, {}", e),
+			None => String::new(),
+		}))
 		.push(
 			Button::new(&mut state.apply_btn_state, Text::new("Apply"))
 				.on_press(RootMessage::ApplyLayout)
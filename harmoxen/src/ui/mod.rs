@@ -9,8 +9,14 @@ mod settings_editor;
 mod sheet_editor;
 
 pub fn build(state: &mut State) -> Element<Message> {
+	let error = match (&state.sheet_editor.error, &state.sheet_editor.layout_error) {
+		(Some(e), _) => e.to_string(),
+		(None, Some(e)) => format!("Invalid layout, {}", e),
+		(None, None) => String::new(),
+	};
+
 	let editor_ui = match state.current_editor {
-		CurrentEditor::SheetEditor => sheet_editor::build(&mut state.sheet_editor, state.theme),
+		CurrentEditor::SheetEditor => sheet_editor::build(&mut state.sheet_editor, &state.tempo, state.theme),
 		CurrentEditor::LayoutEditor => layout_editor::build(&mut state.layout_editor, state.theme),
 		CurrentEditor::SettingsEditor => settings_editor::build(&mut state.settings_editor, state.theme),
 	};
@@ -47,6 +53,8 @@ pub fn build(state: &mut State) -> Element<Message> {
 							.style(state.theme),
 					)
 					.push(Space::new(Length::Fill, Length::Shrink))
+					.push(Text::new(error))
+					.push(Space::new(Length::Units(10), Length::Shrink))
 					.push(
 						Container::new(Parse::new(
 							&mut state.wstates.tempo_input,
@@ -11,12 +11,12 @@ pub fn build<'a>(state: &'a mut State, theme: Theme) -> Element<'a, Message> {
 		.push(DropDown::new(
 			&mut state.wstates.backend_dropdown,
 			match state.backend_id {
-				BackendId::Audio => "Audio",
-				BackendId::Midi(_) => "Midi",
+				BackendId::Audio => "No MIDI output",
+				BackendId::Midi(_) => "MIDI output",
 			},
 			vec![
-				("Audio", Message::ChangeBackend(BackendId::Audio)),
-				("Midi", Message::ChangeBackend(BackendId::Midi(0))),
+				("No MIDI output", Message::ChangeBackend(BackendId::Audio)),
+				("MIDI output", Message::ChangeBackend(BackendId::Midi(0))),
 			],
 		));
 	
@@ -1,22 +1,97 @@
 use crate::{
+	data::{icp, sheet::Limit, track::Target, TempoMap, Track},
 	state::{
-		sheet_editor::{Message, State},
+		sheet_editor::{Message, State, TrackWStates},
 		Message as RootMessage,
 	},
 	widget::{sheet_editor::*, *},
 	Theme,
 };
-use iced::{Element, Length};
+use iced::{button, Align, Element, Length};
 
 const PREVIEW_THICKNESS: u16 = 96;
 const SCROLLBAR_THICKNESS: u16 = 32;
 const TIMELINE_THICKNESS: u16 = 16;
+const LOOP_THICKNESS: u16 = 10;
+const TEMPO_THICKNESS: u16 = 24;
+const ARRANGEMENT_THICKNESS: u16 = 20;
+// lanes above the board
+const HEADER_THICKNESS: u16 =
+	SCROLLBAR_THICKNESS + TIMELINE_THICKNESS + LOOP_THICKNESS + TEMPO_THICKNESS + ARRANGEMENT_THICKNESS;
+
+static TARGETS: [Target; 4] = Target::ALL;
+static LIMITS: [Limit; 7] = Limit::ALL;
 
 fn rootmsg(msg: Message) -> RootMessage {
 	RootMessage::SheetEditor(msg)
 }
 
-pub fn build(state: &mut State, theme: Theme) -> Element<RootMessage> {
+fn track_bar<'a>(
+	wstates: &'a mut Vec<TrackWStates>,
+	add_track: &'a mut button::State,
+	tracks: &'a [Track],
+	curr_track: usize,
+	theme: Theme,
+) -> Element<'a, RootMessage> {
+	wstates.resize_with(tracks.len(), Default::default);
+	let mut row = Row::new().spacing(5).align_items(Align::Center);
+	for (i, (wstate, track)) in wstates.iter_mut().zip(tracks).enumerate() {
+		let name: Element<RootMessage> = if i == curr_track {
+			TextInput::new(&mut wstate.name, "name", &track.name, move |name| {
+				rootmsg(Message::TrackRename(i, name))
+			})
+			.padding(5)
+			.width(Length::Units(96))
+			.style(theme)
+			.into()
+		} else {
+			Tab::new(false, Text::new(&track.name))
+				.on_press(rootmsg(Message::TrackSelect(i)))
+				.style(theme)
+				.into()
+		};
+		row = row
+			.push(name)
+			.push(
+				Tab::new(track.mute, Text::new("M"))
+					.on_press(rootmsg(Message::TrackMute(i)))
+					.style(theme),
+			)
+			.push(
+				Tab::new(track.solo, Text::new("S"))
+					.on_press(rootmsg(Message::TrackSolo(i)))
+					.style(theme),
+			)
+			.push(
+				Tab::new(track.visible, Text::new("V"))
+					.on_press(rootmsg(Message::TrackShow(i)))
+					.style(theme),
+			)
+			.push(
+				PickList::new(&mut wstate.target, &TARGETS[..], Some(track.target), move |target| {
+					rootmsg(Message::TrackSetTarget(i, target))
+				})
+				.style(theme),
+			)
+			.push(Space::with_width(Length::Units(10)));
+	}
+	row.push(
+		Button::new(add_track, Text::new("+"))
+			.on_press(rootmsg(Message::TrackAdd))
+			.style(theme),
+	)
+	.into()
+}
+
+pub fn build<'a>(state: &'a mut State, tempo: &'a TempoMap, theme: Theme) -> Element<'a, RootMessage> {
+	let tracks = track_bar(
+		&mut state.wstates.tracks,
+		&mut state.wstates.add_track,
+		&state.tracks,
+		state.curr_track,
+		theme,
+	);
+
 	let x_scrollbar = Container::new(
 		RangeSlider::horizontal(
 			&mut state.wstates.xrange_slider,
@@ -53,6 +128,27 @@ pub fn build(state: &mut State, theme: Theme) -> Element<RootMessage> {
 	)
 	.height(TIMELINE_THICKNESS.into());
 
+	let loop_editor = Container::new(
+		LoopEditor::new(&mut state.wstates.loop_editor, state.frame, state.playback, &state.layout).style(theme),
+	)
+	.height(LOOP_THICKNESS.into());
+
+	let tempo_editor =
+		Container::new(TempoEditor::new(&mut state.wstates.tempo_editor, state.frame, tempo, &state.layout).style(theme))
+			.height(TEMPO_THICKNESS.into());
+
+	let arrangement = Container::new(
+		Arrangement::new(
+			&mut state.wstates.arrangement,
+			state.frame,
+			&state.sheet,
+			&state.layout,
+			!state.selection.is_empty(),
+		)
+		.style(theme),
+	)
+	.height(ARRANGEMENT_THICKNESS.into());
+
 	let mut editing_area = Stack::new()
 		.push(
 			Board::new(
@@ -62,6 +158,8 @@ pub fn build(state: &mut State, theme: Theme) -> Element<RootMessage> {
 				&state.layout,
 				&state.cursor,
 				&state.selection,
+				&state.hidden_tracks,
+				if state.harmonic_snap { Some(state.snap_limit) } else { None },
 			)
 			.style(theme),
 		)
@@ -76,18 +174,19 @@ pub fn build(state: &mut State, theme: Theme) -> Element<RootMessage> {
 			&state.sheet,
 			&state.frame,
 			&state.selection,
+			&state.hidden_tracks,
 		));
 
 	if let Some(wstate) = &mut state.wstates.interval_input {
 		editing_area = editing_area.push(IntervalInput::new(wstate, &state.sheet, &state.frame));
 	}
 
-	Stack::new()
+	let editor = Stack::new()
 		.push(
 			Row::new()
 				.push(
 					Column::new()
-						.push(Space::with_height((SCROLLBAR_THICKNESS + TIMELINE_THICKNESS).into()))
+						.push(Space::with_height(HEADER_THICKNESS.into()))
 						.push(Preview::new(&mut state.wstates.preview, state.frame).style(theme))
 						.width(PREVIEW_THICKNESS.into()),
 				)
@@ -95,12 +194,71 @@ pub fn build(state: &mut State, theme: Theme) -> Element<RootMessage> {
 					Column::new()
 						.push(
 							Row::new()
-								.push(Column::new().push(x_scrollbar).push(timeline).width(Length::Fill))
+								.push(
+									Column::new()
+										.push(x_scrollbar)
+										.push(timeline)
+										.push(loop_editor)
+										.push(tempo_editor)
+										.push(arrangement)
+										.width(Length::Fill),
+								)
 								.push(Space::with_width(SCROLLBAR_THICKNESS.into())),
 						)
 						.push(Row::new().push(editing_area).push(y_scrollbar)),
 				),
 		)
-		.push(sheet_editor::Shortcuts)
+		.push(sheet_editor::Shortcuts);
+
+	let snap = Row::new()
+		.spacing(5)
+		.align_items(Align::Center)
+		.push(
+			Tab::new(state.playback.looping, Text::new("Loop"))
+				.on_press(rootmsg(Message::ToggleLooping))
+				.style(theme),
+		)
+		.push(
+			Tab::new(state.harmonic_snap, Text::new("Harmonic snap"))
+				.on_press(rootmsg(Message::SnapToggle))
+				.style(theme),
+		)
+		.push(
+			PickList::new(&mut state.wstates.snap_limit, &LIMITS[..], Some(state.snap_limit), |limit| {
+				rootmsg(Message::SnapSetLimit(limit))
+			})
+			.style(theme),
+		);
+
+	// applies to the selected notes, as soon as a valid velocity is typed
+	let mut velocity = Row::new().spacing(5).align_items(Align::Center);
+	if !state.selection.is_empty() {
+		velocity = velocity.push(Text::new("Velocity")).push(
+			Container::new(Parse::new(
+				&mut state.wstates.velocity,
+				move |wstate, data| TextInput::new(wstate, "0 to 1", &data, |s| s).style(theme).padding(5),
+				icp::DEFAULT_VELOCITY.to_string(),
+				|s| {
+					s.parse::<f32>()
+						.ok()
+						.filter(|velocity| (0.0..=1.0).contains(velocity))
+						.map(|velocity| rootmsg(Message::SetVelocity(velocity)))
+				},
+			))
+			.height(Length::Shrink)
+			.width(Length::Units(64)),
+		);
+	}
+
+	Column::new()
+		.push(
+			Row::new()
+				.push(tracks)
+				.push(Space::with_width(Length::Fill))
+				.push(velocity)
+				.push(Space::with_width(Length::Units(10)))
+				.push(snap),
+		)
+		.push(editor)
 		.into()
 }
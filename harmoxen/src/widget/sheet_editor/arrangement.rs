@@ -0,0 +1,285 @@
+use crate::data::{sheet::Interval, Frame2, Layout, Point, Sheet};
+use crate::state::{sheet_editor::Message, Message as RootMessage};
+use crate::util::coord::Coord;
+use iced_graphics::{Backend, Defaults, Primitive, Renderer};
+use iced_native::{
+	event, layout as iced_layout, mouse, overlay, Clipboard, Color, Element, Event, Font, Hasher, HorizontalAlignment,
+	Layout as IcedLayout, Length, Rectangle, Vector, VerticalAlignment, Widget,
+};
+
+use super::interval_input::IntervalTarget;
+use super::marker_editor::StyleSheet;
+use crate::widget::common::{context_menu, ContextMenu};
+
+#[derive(PartialEq)]
+pub enum Action {
+	Idle,
+	Move(usize, f32), // instance, grab offset
+	Context,
+}
+
+pub struct State {
+	action: Action,
+	context_menu: context_menu::State<RootMessage>,
+	context_pos: Option<iced::Point>,
+}
+
+impl Default for State {
+	fn default() -> State {
+		State {
+			action: Action::Idle,
+			context_menu: context_menu::State::default(),
+			context_pos: None,
+		}
+	}
+}
+
+// a lane showing the instances of clips on the timeline
+pub struct Arrangement<'a> {
+	state: &'a mut State,
+	sheet: &'a Sheet,
+	layout: &'a Layout,
+	has_selection: bool,
+	frame: Frame2,
+	style: Box<dyn StyleSheet>,
+}
+
+impl<'a> Arrangement<'a> {
+	pub fn new(state: &'a mut State, frame: Frame2, sheet: &'a Sheet, layout: &'a Layout, has_selection: bool) -> Self {
+		Self {
+			state,
+			frame,
+			sheet,
+			layout,
+			has_selection,
+			style: Default::default(),
+		}
+	}
+
+	pub fn style(mut self, style: impl Into<Box<dyn StyleSheet>>) -> Self {
+		self.style = style.into();
+		self
+	}
+}
+
+impl<'a, B> Widget<RootMessage, Renderer<B>> for Arrangement<'a>
+where
+	B: Backend + iced_graphics::backend::Text + 'static,
+{
+	fn width(&self) -> Length {
+		Length::Fill
+	}
+
+	fn height(&self) -> Length {
+		Length::Fill
+	}
+
+	fn layout(&self, _renderer: &Renderer<B>, limits: &iced_layout::Limits) -> iced_layout::Node {
+		iced_layout::Node::new(limits.max())
+	}
+
+	fn hash_layout(&self, _action: &mut Hasher) {}
+
+	fn on_event(
+		&mut self,
+		event: Event,
+		layout: iced_native::Layout,
+		cursor_position: iced::Point,
+		messages: &mut Vec<RootMessage>,
+		_renderer: &Renderer<B>,
+		_clipboard: Option<&dyn Clipboard>,
+	) -> event::Status {
+		let lbounds = layout.bounds();
+		let lposition: Point = lbounds.position().into();
+		let mouse_pos = Into::<Point>::into(cursor_position) - lposition.to_vec2();
+		let coord = Coord::new(self.frame, lbounds.size());
+		let at = coord.to_board_x(mouse_pos.x);
+
+		let captured = match event {
+			Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+				if let Action::Context = self.state.action {
+					self.state.action = Action::Idle;
+					true
+				} else if lbounds.contains(cursor_position) {
+					if let Some(idx) = self.sheet.get_instance_at(at) {
+						messages.push(Message::InstanceSelect(idx).into());
+						self.state.action = Action::Move(idx, at - self.sheet.instances[idx].offset);
+						true
+					} else {
+						false
+					}
+				} else {
+					false
+				}
+			}
+			Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+				if let Action::Move(idx, grab) = self.state.action {
+					let offset = self.layout.quantize_time((at - grab).max(0.0), false);
+					if offset != self.sheet.instances[idx].offset {
+						messages.push(Message::InstanceMove(idx, offset).into());
+					}
+				}
+				false
+			}
+			Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+				if let Action::Move(..) = self.state.action {
+					self.state.action = Action::Idle;
+				}
+				false
+			}
+			Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+				self.state.action = Action::Idle;
+				if lbounds.contains(cursor_position) {
+					let items = match self.sheet.get_instance_at(at) {
+						Some(idx) => vec![
+							context_menu::Item::new("Select notes", Message::InstanceSelect(idx).into()),
+							context_menu::Item::new("Octave up", Message::InstanceTranspose(idx, Interval::Ratio(2, 1)).into()),
+							context_menu::Item::new(
+								"Octave down",
+								Message::InstanceTranspose(idx, Interval::Ratio(1, 2)).into(),
+							),
+							context_menu::Item::new(
+								"Transpose by...",
+								Message::OpenIntervalInput(IntervalTarget::Instance(idx)).into(),
+							),
+							context_menu::Item::new("Make unique", Message::InstanceMakeUnique(idx).into()),
+							context_menu::Item::new("Delete instance", Message::InstanceDelete(idx).into()),
+						],
+						None => {
+							let at = self.layout.quantize_time(at, false);
+							let mut items = vec![];
+							if self.has_selection {
+								items.push(context_menu::Item::new(
+									"Make clip from selection",
+									Message::ClipCreate.into(),
+								));
+							}
+							for (i, clip) in self.sheet.clips.iter().enumerate() {
+								let text = format!("Insert {}", clip.name);
+								items.push(context_menu::Item::new(&text, Message::ClipInstance(i, at).into()));
+							}
+							items
+						}
+					};
+					if !items.is_empty() {
+						self.state.context_menu = context_menu::State::new(items);
+						self.state.action = Action::Context;
+						self.state.context_pos = Some(cursor_position);
+					}
+					true
+				} else {
+					false
+				}
+			}
+			_ => false,
+		};
+		if captured {
+			event::Status::Captured
+		} else {
+			event::Status::Ignored
+		}
+	}
+
+	fn draw(
+		&self,
+		_renderer: &mut Renderer<B>,
+		_defaults: &Defaults,
+		layout: IcedLayout,
+		cursor_position: iced::Point,
+		_viewport: &Rectangle,
+	) -> (Primitive, mouse::Interaction) {
+		let bounds = layout.bounds();
+		let coord = Coord::new(self.frame, bounds.size());
+		let mouse_pos =
+			coord.to_board_x((Into::<Point>::into(cursor_position) - Into::<Point>::into(bounds.position()).to_vec2()).x);
+
+		let mut blocks = vec![];
+		for instance in &self.sheet.instances {
+			let span = instance.span(&self.sheet.clips);
+			let x0 = bounds.x + coord.to_screen_x(span.0);
+			let x1 = bounds.x + coord.to_screen_x(span.1);
+			let block = Rectangle {
+				x: x0,
+				y: bounds.y + 1.0,
+				width: (x1 - x0).max(1.0),
+				height: bounds.height - 2.0,
+			};
+			blocks.push(Primitive::Quad {
+				bounds: block,
+				background: Color::from_rgba(1.0, 1.0, 1.0, 0.2).into(),
+				border_width: 1.0,
+				border_radius: 2.0,
+				border_color: Color::from_rgba(1.0, 1.0, 1.0, 0.6),
+			});
+			let name = &self.sheet.clips[instance.clip].name;
+			let count = self.sheet.instances.iter().filter(|i| i.clip == instance.clip).count();
+			blocks.push(Primitive::Text {
+				content: if count > 1 {
+					format!("{} ×{}", name, count)
+				} else {
+					name.clone()
+				},
+				size: (bounds.height - 4.0).max(8.0),
+				font: Font::Default,
+				color: Color::WHITE,
+				bounds: Rectangle {
+					x: block.x + 3.0,
+					y: block.center_y(),
+					..block
+				},
+				horizontal_alignment: HorizontalAlignment::Left,
+				vertical_alignment: VerticalAlignment::Center,
+			});
+		}
+
+		let blocks_primitives = Primitive::Clip {
+			bounds,
+			offset: Vector::new(0, 0),
+			content: Box::new(Primitive::Group { primitives: blocks }),
+		};
+		(
+			Primitive::Group {
+				primitives: vec![
+					Primitive::Quad {
+						bounds,
+						background: self.style.active().background,
+						border_width: 0.0,
+						border_radius: 0.0,
+						border_color: Color::TRANSPARENT,
+					},
+					blocks_primitives,
+				],
+			},
+			if let Action::Move(..) = self.state.action {
+				mouse::Interaction::Grabbing
+			} else if bounds.contains(cursor_position) && self.sheet.get_instance_at(mouse_pos).is_some() {
+				mouse::Interaction::Grab
+			} else {
+				mouse::Interaction::Idle
+			},
+		)
+	}
+
+	fn overlay(&mut self, _layout: iced_layout::Layout) -> Option<overlay::Element<RootMessage, Renderer<B>>> {
+		if let Action::Context = self.state.action {
+			Some(
+				ContextMenu::new(&mut self.state.context_menu)
+					.padding(4)
+					.style(self.style.menu())
+					.overlay(self.state.context_pos.unwrap()),
+			)
+		} else {
+			None
+		}
+	}
+}
+
+impl<'a, B> Into<Element<'a, RootMessage, Renderer<B>>> for Arrangement<'a>
+where
+	RootMessage: 'a + Clone,
+	B: Backend + iced_graphics::backend::Text + 'static,
+{
+	fn into(self) -> Element<'a, RootMessage, Renderer<B>> {
+		Element::new(self)
+	}
+}
@@ -112,6 +112,58 @@ impl<'a> Board<'a> {
 				let period = pattern.period();
 				let min_freq = 2f32.powf(coord.frame.y.view.0);
 				let max_freq = 2f32.powf(coord.frame.y.view.1);
+
+				// the lines are straight once the marker is done morphing from the previous one
+				let mut s_start = s_start;
+				let marker = markers[i];
+				if let Some(from) = layout.get_previous_marker(marker).and_then(|m| m.pattern.freq.as_ref()) {
+					if marker.morph > 0.0 {
+						let s_morph_end = coord.to_screen_x(marker.at + marker.morph).min(s_end);
+						let (from_min, from_max) = degree_range(from, min_freq, max_freq);
+						let (to_min, to_max) = degree_range(pattern, min_freq, max_freq);
+						for degree in from_min.min(to_min)..from_max.max(to_max) {
+							let is_root = degree.rem_euclid(pattern.size() as i32) == 0;
+							if !is_root && view_height >= 4.0 {
+								continue;
+							}
+							let from_y = (from.base * from.degree_ratio(degree)).log2();
+							let to_y = (pattern.base * pattern.degree_ratio(degree)).log2();
+							let s_y = |s_x: f32| {
+								let t = ((coord.to_board_x(s_x) - marker.at) / marker.morph).max(0.0).min(1.0);
+								coord.to_screen_y(from_y + (to_y - from_y) * t)
+							};
+							let path = Path::new(|b| {
+								let mut s_x = s_start.max(0.0);
+								let s_x_end = s_morph_end.min(size.width);
+								b.move_to([s_x, s_y(s_x)].into());
+								while s_x < s_x_end {
+									s_x = (s_x + MORPH_STEP).min(s_x_end);
+									b.line_to([s_x, s_y(s_x)].into());
+								}
+							});
+							let (width, color) = if is_root {
+								(
+									4.0,
+									Color {
+										a: 1.0,
+										..style.root_line_color
+									},
+								)
+							} else {
+								(2.0, Color::from_rgba(0.4, 0.4, 0.4, 0.5))
+							};
+							frame.stroke(
+								&path,
+								Stroke {
+									width,
+									color,
+									..Default::default()
+								},
+							);
+						}
+						s_start = s_morph_end;
+					}
+				}
 				let min = (min_freq / pattern.base).log(period).floor() as isize;
 				let max = (max_freq / pattern.base).log(period).ceil() as isize;
 				for i in min..max {
@@ -158,3 +210,15 @@ impl<'a> Board<'a> {
 		Primitive::Group { primitives }
 	}
 }
+
+// horizontal distance between the points of a morphing line
+const MORPH_STEP: f32 = 4.0;
+
+// degrees of a pattern between two frequencies
+fn degree_range(pattern: &FreqPattern, min_freq: f32, max_freq: f32) -> (i32, i32) {
+	let period = pattern.period();
+	let n = pattern.size() as i32;
+	let min = (min_freq / pattern.base).log(period).floor() as i32;
+	let max = (max_freq / pattern.base).log(period).ceil() as i32;
+	(min * n, max * n + 1)
+}
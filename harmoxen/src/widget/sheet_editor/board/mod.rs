@@ -1,19 +1,20 @@
-use crate::consts::NOTE_HEIGHT;
+use crate::consts::{NOTE_HEIGHT, RATIO_TOLERANCE};
 use crate::data::{
 	icp,
 	layout::Layout,
-	sheet::{Index, Interval, Note, Pitch, Sheet},
-	Frame2, Line, Point, Rect, Vec2,
+	sheet::{BendPoint, Index, Interval, Limit, Note, Pitch, Sheet, Snap, Transform},
+	Frame2, Line, Point, Range, Rect, Vec2,
 };
 use crate::state::{sheet_editor::Message, Message as RootMessage};
 use crate::util::coord::Coord;
 use crate::{
 	backend,
-	widget::{context_menu, ContextMenu},
+	widget::{context_menu, sheet_editor::interval_input::IntervalTarget, ContextMenu},
 };
 use iced_graphics::{Backend, Defaults, Primitive, Renderer};
 use iced_native::{
-	event, layout as iced_layout, mouse, overlay, Clipboard, Color, Element, Event, Hasher, Length, Rectangle, Size, Widget,
+	event, keyboard, layout as iced_layout, mouse, overlay, Clipboard, Color, Element, Event, Hasher, Length, Rectangle, Size,
+	Widget,
 };
 use std::collections::{HashMap, HashSet};
 use std::time::Instant;
@@ -23,6 +24,7 @@ const NOTE_SCALE_KNOB: f32 = 32.0;
 // mod interval_input;
 mod layout;
 mod notes;
+mod snap;
 mod style;
 
 pub use style::{Style, StyleSheet};
@@ -50,6 +52,7 @@ pub enum Action {
 	Idle,
 	Move(Index, HashMap<Index, Vec2>, Rect), // root note, offsets to mouse, extent of selection around mouse
 	Scale(Index, HashMap<Index, f32>),       // root note, original lengths of notes
+	Bend(Index, f32),                        // note, time of the edited bend point
 	DeleteNotes(Point),
 	Context {
 		menu: context_menu::State<RootMessage>,
@@ -63,6 +66,8 @@ pub struct State {
 	note_len: f32,
 	last_left_click: (Point, Instant),
 	action_effective: bool,
+	alt: bool,
+	snap: Option<Snap>, // ratio the moved note is snapped to
 }
 
 impl Default for State {
@@ -73,6 +78,8 @@ impl Default for State {
 			note_len: 1.0,
 			last_left_click: (Point::new(f32::INFINITY, f32::INFINITY), Instant::now()),
 			action_effective: false,
+			alt: false,
+			snap: None,
 		}
 	}
 }
@@ -92,6 +99,8 @@ pub struct Board<'a> {
 	layout: &'a Layout,
 	cursor: &'a f32,
 	selection: &'a HashSet<Index>,
+	hidden_tracks: &'a HashSet<usize>,
+	snap: Option<Limit>,
 	style: Box<dyn StyleSheet>,
 }
 
@@ -103,6 +112,8 @@ impl<'a> Board<'a> {
 		layout: &'a Layout,
 		cursor: &'a f32,
 		selection: &'a HashSet<Index>,
+		hidden_tracks: &'a HashSet<usize>,
+		snap: Option<Limit>,
 	) -> Self {
 		Self {
 			state,
@@ -111,6 +122,8 @@ impl<'a> Board<'a> {
 			layout,
 			cursor,
 			selection,
+			hidden_tracks,
+			snap,
 			style: Default::default(),
 		}
 	}
@@ -126,6 +139,7 @@ impl<'a> Board<'a> {
 			self.state.action_effective = false;
 		}
 		self.state.action = Action::Idle;
+		self.state.snap = None;
 		messages.push(RootMessage::Backend(backend::Event::ICP(icp::Event::NoteStop(2000))));
 	}
 }
@@ -174,6 +188,8 @@ where
 		let sheet = self.sheet;
 		let layout = self.layout;
 		let selection = self.selection;
+		let hidden_tracks = self.hidden_tracks;
+		let snap = self.snap;
 
 		if let Action::Context { .. } = state.action {
 			if let Event::Mouse(mouse::Event::ButtonPressed(_)) = event {
@@ -186,23 +202,25 @@ where
 		match event {
 			Event::Mouse(mouse::Event::ButtonPressed(btn)) if lbounds.contains(cursor_position) => {
 				let pos = coord.to_board_p(mouse_pos);
-				state.hover = get_hover(pos, &coord, &sheet);
+				state.hover = get_hover(pos, &coord, &sheet, hidden_tracks);
 				if btn == mouse::Button::Left {
 					let is_double_click =
 						mouse_pos == state.last_left_click.0 && state.last_left_click.1.elapsed().as_millis() < 500;
 					state.last_left_click = (mouse_pos, Instant::now());
 					if is_double_click {
-						if let Some(id) = get_hover(pos, &coord, &self.sheet).note_idx() {
+						if let Some(id) = get_hover(pos, &coord, &self.sheet, hidden_tracks).note_idx() {
 							let mut note = sheet.get_note(id).unwrap();
+							let marker = layout.get_marker_idx_at(note.start);
 							note.start += pos.x;
-							let items = vec![
+							let is_bent = note.is_bent();
+							let axis = sheet.get_freq(note.pitch, note.start);
+							let mut items = vec![
 								context_menu::Item::new(
 									"Add relative note",
 									Message::NoteAdd(
 										Note {
-											start: pos.x,
-											length: self.state.note_len,
 											pitch: Pitch::Relative(id, Interval::Ratio(3, 2)),
+											..Note::new(pos, self.state.note_len)
 										},
 										false,
 									)
@@ -211,24 +229,77 @@ where
 								context_menu::Item::new("Duplicate note", Message::NoteAdd(note, false).into()),
 								context_menu::Item::new("Delete note", Message::NoteDelete(id).into()),
 							];
+							if is_bent {
+								items.push(context_menu::Item::new("Clear bend", Message::NoteSetBend(id, vec![]).into()));
+							}
+							if let Some(marker) = marker.filter(|&i| layout.markers[i].pattern.freq.is_some()) {
+								items.push(context_menu::Item::new(
+									"Anchor layout to this note",
+									Message::SetMarkerAnchor(marker, Some(id)).into(),
+								));
+							}
+							if self.selection.contains(&id) {
+								let origin = *self.cursor;
+								let transforms = [
+									("Transpose octave up", Transform::Transpose(Interval::Ratio(2, 1))),
+									("Transpose octave down", Transform::Transpose(Interval::Ratio(1, 2))),
+									("Stretch ×2 from cursor", Transform::Stretch { origin, factor: 2.0 }),
+									("Stretch ×½ from cursor", Transform::Stretch { origin, factor: 0.5 }),
+									("Retrograde", Transform::Retrograde),
+									("Invert around this note", Transform::Invert(axis)),
+									("Quantize", Transform::Quantize),
+									("Attach to nearby notes", Transform::Relativize(RATIO_TOLERANCE)),
+								];
+								for &(text, transform) in &transforms {
+									items.push(context_menu::Item::new(text, Message::Transform(transform).into()));
+								}
+								// any interval or factor can be typed as well
+								let typed = [
+									("Transpose by...", IntervalTarget::Transpose(id)),
+									("Stretch from cursor by...", IntervalTarget::Stretch(id, origin)),
+								];
+								for &(text, target) in &typed {
+									items.push(context_menu::Item::new(text, Message::OpenIntervalInput(target).into()));
+								}
+								if self.selection.len() > 1 {
+									// the period is typed, starting from that of the current scale
+									let scales = [
+										("Scale from selection, in ratios...", None, false),
+										("Scale from selection, in cents...", None, true),
+										("Scale from selection on this note...", Some(id), false),
+									];
+									for &(text, base, cents) in &scales {
+										let target = IntervalTarget::Scale {
+											note: id,
+											base,
+											cents,
+											at: origin,
+										};
+										items.push(context_menu::Item::new(text, Message::OpenIntervalInput(target).into()));
+									}
+								}
+							}
 							self.state.action = Action::Context {
 								menu: context_menu::State::new(items),
 								pos: cursor_position,
 							};
 						}
+					} else if let (true, Some(idx)) = (state.alt, state.hover.note_idx()) {
+						let note = sheet.get_note(idx).unwrap();
+						state.action = Action::Bend(idx, (pos.x - note.start).max(0.0).min(note.length));
 					} else {
 						match state.hover {
 							Hover::Idle => {
 								let note = layout.quantize_note(Note::new(pos, state.note_len));
 								if sheet
-									.get_note_at(Point::new(note.start + 1e-4, note.y(&sheet)), 0.01)
+									.get_note_at(Point::new(note.start + 1e-4, note.y(&sheet)), 0.01, hidden_tracks)
 									.is_none()
 								{
+									let freq = sheet.get_freq(note.pitch, note.start);
 									messages.push(Message::NoteAdd(note, true).into());
-									messages.push(RootMessage::Backend(backend::Event::ICP(icp::Event::NotePlay(icp::Note {
-										id: 2000,
-										freq: sheet.get_freq(note.pitch),
-									}))));
+									messages.push(RootMessage::Backend(backend::Event::ICP(icp::Event::NotePlay(
+										icp::Note::new(2000, freq),
+									))));
 								}
 								if selection.len() > 0 {
 									messages.push(Message::SetSelection(HashSet::new()).into());
@@ -250,17 +321,16 @@ where
 								} else {
 									let note = sheet.get_note(idx).unwrap();
 									if let Pitch::Relative(_, _) = note.pitch {
-										messages.push(Message::OpenIntervalInput(idx).into());
+										messages.push(Message::OpenIntervalInput(IntervalTarget::Note(idx)).into());
 									}
 									let mut notes = HashMap::new();
 									notes.insert(idx, note.start_pt(&sheet).to_vec2() - pos.to_vec2());
 									state.action = Action::Move(idx, notes, note.rect(&sheet, 0.0) - pos.to_vec2());
 									let note = sheet.get_note(idx).unwrap();
 									state.note_len = note.length;
-									messages.push(RootMessage::Backend(backend::Event::ICP(icp::Event::NotePlay(icp::Note {
-										id: 2000,
-										freq: sheet.get_freq(note.pitch),
-									}))));
+									messages.push(RootMessage::Backend(backend::Event::ICP(icp::Event::NotePlay(
+										icp::Note::new(2000, sheet.get_freq(note.pitch, note.start)),
+									))));
 								}
 							}
 							Hover::Scale(idx) => {
@@ -279,7 +349,7 @@ where
 						}
 					}
 				} else if btn == mouse::Button::Right {
-					if let Some(idx) = sheet.get_note_at(pos, coord.to_board_h(NOTE_HEIGHT)) {
+					if let Some(idx) = sheet.get_note_at(pos, coord.to_board_h(NOTE_HEIGHT), hidden_tracks) {
 						state.action_effective = true;
 						self.stop_action(messages, &mut history_save);
 						messages.push(Message::NoteDelete(idx).into());
@@ -300,7 +370,23 @@ where
 						let root_offset = offsets[&root_idx];
 						let mut root_start_pt = pos + root_offset;
 						root_start_pt.x = root_start_pt.x.max(0.0);
-						let mut anchor = layout.quantize_position(root_start_pt) - root_offset;
+						let mut quantized = layout.quantize_position(root_start_pt);
+						state.snap = None;
+						if let Some(limit) = snap {
+							let root = sheet.get_note(*root_idx).unwrap();
+							// a note on a degree leaves the scale when it snaps off it
+							if matches!(root.pitch, Pitch::Absolute(_) | Pitch::Degree { .. }) {
+								let range = Range(quantized.x, quantized.x + root.length);
+								let freq = 2f32.powf(root_start_pt.y);
+								if let Some(snap) = sheet.snap_freq(freq, range, limit, |idx| {
+									offsets.contains_key(&idx) || hidden_tracks.contains(&sheet.notes[idx].track)
+								}) {
+									quantized.y = snap.freq.log2();
+									state.snap = Some(snap);
+								}
+							}
+						}
+						let mut anchor = quantized - root_offset;
 						anchor.x = anchor.x.max(-bounds.x0);
 						for (idx, offset) in offsets {
 							let note = sheet.get_note(*idx).unwrap();
@@ -308,12 +394,11 @@ where
 							if note.start != pos.x || note.y(&sheet) != pos.y {
 								messages.push(Message::NoteMove(*idx, pos).into());
 								state.action_effective = true;
-								if sheet.get_y(note.pitch) != pos.y {
+								if note.y(&sheet) != pos.y {
 									messages.push(RootMessage::Backend(backend::Event::ICP(icp::Event::NoteStop(2000))));
-									messages.push(RootMessage::Backend(backend::Event::ICP(icp::Event::NotePlay(icp::Note {
-										id: 2000,
-										freq: sheet.get_freq(Pitch::Absolute(2f32.powf(pos.y))),
-									}))));
+									messages.push(RootMessage::Backend(backend::Event::ICP(icp::Event::NotePlay(
+										icp::Note::new(2000, 2f32.powf(pos.y)),
+									))));
 								}
 							}
 						}
@@ -330,8 +415,20 @@ where
 							state.note_len = time - note.start;
 						}
 					}
+					Action::Bend(idx, at) => {
+						let mut note = sheet.get_note(*idx).unwrap();
+						let offset = 2f32.powf(pos.y) / sheet.get_freq_at(&note, note.start + *at);
+						note.set_bend_point(BendPoint {
+							at: *at,
+							offset: Interval::Cents(1200.0 * offset.log2()),
+						});
+						messages.push(Message::NoteSetBend(*idx, note.bend).into());
+						state.action_effective = true;
+					}
 					Action::DeleteNotes(ref mut prev_pos) => {
-						for idx in sheet.get_notes_along(Line::new(*prev_pos, pos), coord.to_board_h(NOTE_HEIGHT)) {
+						for idx in
+							sheet.get_notes_along(Line::new(*prev_pos, pos), coord.to_board_h(NOTE_HEIGHT), hidden_tracks)
+						{
 							state.action_effective = true;
 							messages.push(Message::NoteDelete(idx).into());
 						}
@@ -339,7 +436,10 @@ where
 					}
 					_ => {}
 				}
-				state.hover = get_hover(pos, &coord, &sheet);
+				state.hover = get_hover(pos, &coord, &sheet, hidden_tracks);
+			}
+			Event::Keyboard(keyboard::Event::ModifiersChanged(mods)) => {
+				state.alt = mods.alt;
 			}
 			Event::Mouse(mouse::Event::ButtonReleased(_)) => match self.state.action {
 				Action::Context { .. } => {}
@@ -368,6 +468,8 @@ where
 			self.draw_layout(size, &coord, self.layout, style),
 			// Draw notes
 			self.draw_notes(size, &coord, style),
+			// Draw the ratio the moved note is snapped to
+			self.draw_snap(&coord, style),
 			// Draw cursor
 			Primitive::Quad {
 				bounds: Rect::from_point_size(Point::new(coord.to_screen_x(*self.cursor), 0.0), Size::new(1.0, size.height))
@@ -406,8 +508,8 @@ where
 	}
 }
 
-fn get_hover(pos: Point, coord: &Coord, sheet: &Sheet) -> Hover {
-	let hovered_note_idx = sheet.get_note_at(pos, coord.to_board_h(NOTE_HEIGHT));
+fn get_hover(pos: Point, coord: &Coord, sheet: &Sheet, hidden_tracks: &HashSet<usize>) -> Hover {
+	let hovered_note_idx = sheet.get_note_at(pos, coord.to_board_h(NOTE_HEIGHT), hidden_tracks);
 	match hovered_note_idx {
 		None => Hover::Idle,
 		Some(idx) => {
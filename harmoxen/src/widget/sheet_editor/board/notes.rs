@@ -9,7 +9,10 @@ impl<'a> Board<'a> {
 		let mut frame = Frame::new(size);
 		let sheet = &self.sheet;
 		for (index, note) in sheet.get_notes() {
-			let pos = sheet.get_y(note.pitch);
+			if self.hidden_tracks.contains(&note.track) {
+				continue;
+			}
+			let pos = note.y(&sheet);
 			let s_pos = coord.to_screen_y(pos);
 
 			let s_start = coord.to_screen_x(note.start);
@@ -17,7 +20,7 @@ impl<'a> Board<'a> {
 
 			if let Pitch::Relative(root, _) = note.pitch {
 				let root = sheet.get_note(root).unwrap();
-				let root_s_freq = coord.to_screen_y(sheet.get_y(root.pitch));
+				let root_s_freq = coord.to_screen_y(root.y(&sheet));
 				let path = if note.start < root.start || note.start > root.end() {
 					let endpoint = coord.to_screen_x(if note.start < root.start { root.start } else { root.end() });
 					Path::new(|b| {
@@ -58,8 +61,12 @@ impl<'a> Board<'a> {
 				Action::Move(id, _, _) if id == index => {
 					color = style.note_highlight;
 				}
+				Action::Bend(id, _) if id == index => {
+					color = style.note_highlight;
+				}
 				_ => {}
 			}
+			color.a *= 0.4 + 0.6 * note.velocity.max(0.0).min(1.0);
 			let path = Path::rectangle([p0.x, p0.y - NOTE_HEIGHT / 2.0].into(), [s_length, NOTE_HEIGHT].into());
 			frame.fill(&path, color);
 
@@ -77,6 +84,31 @@ impl<'a> Board<'a> {
 				let path = Path::rectangle(rect.position().into(), rect.size());
 				frame.fill(&path, style.note_highlight);
 			}
+
+			// draw pitch envelope
+			if sheet.is_gliding(&note) {
+				let nsteps = (s_length / 4.0).ceil().max(1.0) as usize;
+				let path = Path::new(|b| {
+					for i in 0..=nsteps {
+						let time = note.start + note.length * i as f32 / nsteps as f32;
+						let s_x = coord.to_screen_x(time);
+						let s_y = coord.to_screen_y(sheet.get_note_freq_at(&note, time).log2());
+						if i == 0 {
+							b.move_to([s_x, s_y].into());
+						} else {
+							b.line_to([s_x, s_y].into());
+						}
+					}
+				});
+				frame.stroke(
+					&path,
+					Stroke {
+						width: 2.0,
+						color: style.note_highlight,
+						..Default::default()
+					},
+				);
+			}
 		}
 		frame.into_geometry().into_primitive()
 	}
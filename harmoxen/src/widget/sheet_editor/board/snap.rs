@@ -0,0 +1,60 @@
+use super::{Action, Board, Style, NOTE_HEIGHT};
+use crate::util::coord::Coord;
+use iced::canvas::{Frame, Path, Stroke};
+use iced_graphics::Primitive;
+use iced_native::{Font, HorizontalAlignment, Rectangle, VerticalAlignment};
+
+impl<'a> Board<'a> {
+	// ghost line from the sounding note to the ratio the moved note is snapped to
+	pub fn draw_snap(&self, coord: &Coord, style: Style) -> Primitive {
+		let (idx, snap) = match (&self.state.action, self.state.snap) {
+			(Action::Move(idx, _, _), Some(snap)) => (*idx, snap),
+			_ => return Primitive::None,
+		};
+		let (note, root) = match (self.sheet.get_note(idx), self.sheet.get_note(snap.root)) {
+			(Some(note), Some(root)) => (note, root),
+			_ => return Primitive::None,
+		};
+		let mut color = style.root_line_color;
+		color.a = 0.6;
+
+		let s_start = coord.to_screen_x(note.start);
+		let s_end = coord.to_screen_x(note.end());
+		let s_y = coord.to_screen_y(snap.freq.log2());
+		let s_root_y = coord.to_screen_y(root.y(&self.sheet));
+		let mut frame = Frame::new(coord.size);
+		let path = Path::new(|b| {
+			b.move_to([s_start, s_root_y].into());
+			b.line_to([s_start, s_y].into());
+			b.line_to([s_end, s_y].into());
+		});
+		frame.stroke(
+			&path,
+			Stroke {
+				width: 1.0,
+				color,
+				..Default::default()
+			},
+		);
+
+		Primitive::Group {
+			primitives: vec![
+				frame.into_geometry().into_primitive(),
+				Primitive::Text {
+					content: snap.interval.to_string(),
+					size: NOTE_HEIGHT * 0.75,
+					font: Font::Default,
+					color,
+					bounds: Rectangle {
+						x: s_end + 4.0,
+						y: s_y,
+						width: 100.0,
+						height: NOTE_HEIGHT,
+					},
+					horizontal_alignment: HorizontalAlignment::Left,
+					vertical_alignment: VerticalAlignment::Center,
+				},
+			],
+		}
+	}
+}
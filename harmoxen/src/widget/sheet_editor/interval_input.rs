@@ -1,5 +1,5 @@
 use crate::data::{
-	sheet::{Index, Interval, Pitch},
+	sheet::{Index, Interval, Pitch, Transform},
 	Frame2, Point, Sheet,
 };
 use crate::state::{sheet_editor::Message, Message as RootMessage};
@@ -7,37 +7,94 @@ use crate::util::coord::Coord;
 use iced_graphics::{backend, Backend, Defaults, Primitive, Renderer};
 use iced_native::widget::*;
 use iced_native::{
-	event, layout as iced_layout, mouse, Clipboard, Element, Event, Hasher, Layout as IcedLayout, Length, Rectangle, Widget,
+	event, layout as iced_layout, mouse, Clipboard, Color, Element, Event, Font, Hasher, HorizontalAlignment,
+	Layout as IcedLayout, Length, Rectangle, VerticalAlignment, Widget,
 };
 
+// what the typed interval is used for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntervalTarget {
+	Note(Index),     // interval of a relative note to its root, changed as it is typed
+	Instance(usize), // transposition of an instance, applied once the interval is submitted
+	// transformations of the selection, shown next to one of its notes and applied once submitted
+	Transpose(Index),
+	Stretch(Index, f32), // from a time, by the value of the interval
+	// period of a scale made from the selection, starting from that of the layout at a time
+	Scale {
+		note: Index,
+		base: Option<Index>,
+		cents: bool,
+		at: f32,
+	},
+}
+
 pub struct State {
 	internal: InternalState,
 	text_input: text_input::State,
 }
 impl State {
-	pub fn new(sheet: &Sheet, idx: Index) -> Self {
-		let note = sheet.get_note(idx).expect("tried to input interval for dead note");
-		match note.pitch {
-			Pitch::Relative(_, interval) => Self {
+	pub fn new(sheet: &Sheet, target: IntervalTarget) -> Self {
+		match target {
+			IntervalTarget::Note(idx) => {
+				let note = sheet.get_note(idx).expect("tried to input interval for dead note");
+				match note.pitch {
+					Pitch::Relative(_, interval) => Self {
+						internal: InternalState {
+							text: interval.to_string(),
+							target,
+						},
+						text_input: Default::default(),
+					},
+					Pitch::Absolute(_) | Pitch::Degree { .. } => panic!("tried to input interval for absolute note"),
+				}
+			}
+			// there is nothing to edit, the interval is typed right away
+			IntervalTarget::Instance(_) | IntervalTarget::Transpose(_) | IntervalTarget::Stretch(..) => Self {
 				internal: InternalState {
-					text: interval.to_string(),
-					idx,
+					text: String::new(),
+					target,
 				},
-				text_input: Default::default(),
+				text_input: text_input::State::focused(),
+			},
+			IntervalTarget::Scale { at, .. } => Self {
+				internal: InternalState {
+					text: sheet.layout().period_at(at).to_string(),
+					target,
+				},
+				text_input: text_input::State::focused(),
 			},
-			Pitch::Absolute(_) => panic!("tried to input interval for absolute note"),
+		}
+	}
+
+	pub fn target(&self) -> IntervalTarget {
+		self.internal.target
+	}
+
+	// whether what the interval is for is still there
+	pub fn is_valid(&self, sheet: &Sheet) -> bool {
+		match self.internal.target {
+			IntervalTarget::Note(idx) | IntervalTarget::Transpose(idx) | IntervalTarget::Stretch(idx, _) => {
+				sheet.notes.contains(idx)
+			}
+			IntervalTarget::Scale { note, base, .. } => {
+				sheet.notes.contains(note) && base.map_or(true, |base| sheet.notes.contains(base))
+			}
+			IntervalTarget::Instance(instance) => instance < sheet.instances.len(),
 		}
 	}
 }
 
 struct InternalState {
 	text: String,
-	idx: Index,
+	target: IntervalTarget,
 }
 // aaa
 
 #[derive(Clone)]
-struct IntervalChange(String);
+enum IntervalChange {
+	Text(String),
+	Submit,
+}
 
 pub struct IntervalInput<'a, B>
 where
@@ -55,7 +112,8 @@ where
 {
 	pub fn new(state: &'a mut State, sheet: &'a Sheet, frame: &'a Frame2) -> Self {
 		let internal = &mut state.internal;
-		let text_input = TextInput::new(&mut state.text_input, "2/1", &internal.text, |v| IntervalChange(v));
+		let text_input = TextInput::new(&mut state.text_input, "2/1", &internal.text, IntervalChange::Text)
+			.on_submit(IntervalChange::Submit);
 		Self {
 			state: internal,
 			text_input,
@@ -82,11 +140,28 @@ where
 		let state = &*self.state;
 		let sheet = self.sheet;
 		let frame = *self.frame;
-		let note = sheet.get_note(state.idx).unwrap();
-		if let Pitch::Relative(root, _) = note.pitch {
+		// between a relative note and its root, at the start of a selected note, or at the top left of an instance
+		let position = match state.target {
+			IntervalTarget::Note(idx) => {
+				let note = sheet.get_note(idx).unwrap();
+				match note.pitch {
+					Pitch::Relative(root, _) => {
+						let root = sheet.get_note(root).unwrap();
+						Some(Point::new(note.start, (note.y(sheet) + root.y(sheet)) / 2.0))
+					}
+					Pitch::Absolute(_) | Pitch::Degree { .. } => None,
+				}
+			}
+			IntervalTarget::Transpose(idx) | IntervalTarget::Stretch(idx, _) | IntervalTarget::Scale { note: idx, .. } => {
+				sheet.get_note(idx).map(|note| note.start_pt(sheet))
+			}
+			IntervalTarget::Instance(instance) => sheet
+				.instances
+				.get(instance)
+				.map(|instance| Point::new(instance.span(&sheet.clips).0, frame.y.view.1)),
+		};
+		if let Some(position) = position {
 			let coord = Coord::new(frame, limits.max());
-			let root = sheet.get_note(root).unwrap();
-			let position = Point::new(note.start, (sheet.get_y(note.pitch) + sheet.get_y(root.pitch)) / 2.0);
 			let screen_pos = coord.to_screen_p(position);
 			let mut node = self.text_input.layout(renderer, &iced_layout::Limits::NONE.max_width(100));
 			node.move_to(screen_pos.into());
@@ -100,11 +175,27 @@ where
 		struct Marker;
 		TypeId::of::<Marker>().hash(state);
 
-		let note = self.sheet.get_note(self.state.idx).unwrap();
-		self.sheet.get_y(note.pitch).to_bits().hash(state);
-		if let Pitch::Relative(root_idx, _) = note.pitch {
-			let root = self.sheet.get_note(root_idx).unwrap();
-			self.sheet.get_y(root.pitch).to_bits().hash(state);
+		match self.state.target {
+			IntervalTarget::Note(idx) => {
+				let note = self.sheet.get_note(idx).unwrap();
+				note.y(self.sheet).to_bits().hash(state);
+				if let Pitch::Relative(root_idx, _) = note.pitch {
+					let root = self.sheet.get_note(root_idx).unwrap();
+					root.y(self.sheet).to_bits().hash(state);
+				}
+			}
+			IntervalTarget::Transpose(idx) | IntervalTarget::Stretch(idx, _) | IntervalTarget::Scale { note: idx, .. } => {
+				if let Some(note) = self.sheet.get_note(idx) {
+					let start = note.start_pt(self.sheet);
+					start.x.to_bits().hash(state);
+					start.y.to_bits().hash(state);
+				}
+			}
+			IntervalTarget::Instance(instance) => {
+				if let Some(instance) = self.sheet.instances.get(instance) {
+					instance.offset.to_bits().hash(state);
+				}
+			}
 		}
 
 		self.text_input.hash_layout(state);
@@ -127,16 +218,38 @@ where
 				.text_input
 				.on_event(event, layout, cursor_position, &mut msgs, renderer, clipboard);
 
-			if let Some(IntervalChange(text)) = msgs.pop() {
-				state.text = text.clone();
-				if let Ok(value) = text.parse::<Interval>() {
-					let note = self.sheet.get_note(state.idx).expect("tried to change interval of dead note");
-					if let Pitch::Relative(root, _) = note.pitch {
-						messages.push(Message::NoteSetPitch(state.idx, Pitch::Relative(root, value)).into());
-					} else {
-						panic!("tried to change interval of absolute note");
+			match (msgs.pop(), state.target) {
+				(Some(IntervalChange::Text(text)), IntervalTarget::Note(idx)) => {
+					state.text = text.clone();
+					if let Ok(value) = text.parse::<Interval>() {
+						let note = self.sheet.get_note(idx).expect("tried to change interval of dead note");
+						if let Pitch::Relative(root, _) = note.pitch {
+							messages.push(Message::NoteSetPitch(idx, Pitch::Relative(root, value)).into());
+						} else {
+							panic!("tried to change interval of absolute note");
+						}
+					}
+				}
+				(Some(IntervalChange::Text(text)), _) => state.text = text,
+				(Some(IntervalChange::Submit), target) => {
+					if let Ok(value) = state.text.parse::<Interval>() {
+						let msg = match target {
+							IntervalTarget::Note(_) => None,
+							IntervalTarget::Instance(instance) => Some(Message::InstanceTranspose(instance, value)),
+							IntervalTarget::Transpose(_) => Some(Message::Transform(Transform::Transpose(value))),
+							IntervalTarget::Stretch(_, origin) => Some(Message::Transform(Transform::Stretch {
+								origin,
+								factor: value.to_f32(),
+							})),
+							IntervalTarget::Scale { base, cents, .. } => Some(Message::ScaleFromSelection(base, cents, value)),
+						};
+						if let Some(msg) = msg {
+							messages.push(msg.into());
+							messages.push(Message::CloseIntervalInput.into());
+						}
 					}
 				}
+				(None, _) => {}
 			}
 			status
 		}
@@ -150,13 +263,42 @@ where
 		cursor_position: iced::Point,
 		viewport: &Rectangle,
 	) -> (Primitive, mouse::Interaction) {
-		Widget::draw(
-			&self.text_input,
-			renderer,
-			defaults,
-			layout.children().next().unwrap(),
-			cursor_position,
-			viewport,
+		let layout = layout.children().next().unwrap();
+		let (input, interaction) = Widget::draw(&self.text_input, renderer, defaults, layout, cursor_position, viewport);
+		let note = match self.state.target {
+			IntervalTarget::Note(idx) => self.sheet.get_note(idx).unwrap(),
+			_ => return (input, interaction),
+		};
+		let chained = match note.pitch {
+			Pitch::Relative(root, _) => self
+				.sheet
+				.get_note(root)
+				.map_or(false, |root| matches!(root.pitch, Pitch::Relative(..))),
+			Pitch::Absolute(_) | Pitch::Degree { .. } => false,
+		};
+		if !chained {
+			return (input, interaction);
+		}
+		// show the accumulated interval when the root is itself relative
+		let (_, total) = self.sheet.get_root_interval(note.pitch).unwrap();
+		let bounds = layout.bounds();
+		let label = Primitive::Text {
+			content: format!("= {}", total.simplify()),
+			size: 16.0,
+			font: Font::Default,
+			color: Color::WHITE,
+			bounds: Rectangle {
+				y: bounds.y + bounds.height,
+				..bounds
+			},
+			horizontal_alignment: HorizontalAlignment::Left,
+			vertical_alignment: VerticalAlignment::Top,
+		};
+		(
+			Primitive::Group {
+				primitives: vec![input, label],
+			},
+			interaction,
 		)
 	}
 }
@@ -0,0 +1,266 @@
+use crate::data::{Frame2, Layout, Playback, Point, Range};
+use crate::state::{sheet_editor::Message, Message as RootMessage};
+use crate::util::coord::Coord;
+use iced_graphics::{Backend, Defaults, Primitive, Renderer};
+use iced_native::{
+	event, layout as iced_layout, mouse, overlay, Clipboard, Color, Element, Event, Hasher, Layout as IcedLayout, Length,
+	Rectangle, Vector, Widget,
+};
+
+use super::marker_editor::StyleSheet;
+use crate::widget::common::{context_menu, ContextMenu};
+
+// distance in pixels under which an edge of the region can be grabbed
+const EDGE_GRAB: f32 = 4.0;
+
+#[derive(PartialEq)]
+pub enum Action {
+	Idle,
+	Create(f32), // time where the drag started
+	MoveStart,
+	MoveEnd,
+	Move(f32), // grab offset
+	Context,
+}
+
+#[derive(PartialEq)]
+enum Hover {
+	Start,
+	End,
+	Inside,
+	Outside,
+}
+
+pub struct State {
+	action: Action,
+	context_menu: context_menu::State<RootMessage>,
+	context_pos: Option<iced::Point>,
+}
+
+impl Default for State {
+	fn default() -> State {
+		State {
+			action: Action::Idle,
+			context_menu: context_menu::State::default(),
+			context_pos: None,
+		}
+	}
+}
+
+// a lane showing the loop region, whose edges can be dragged
+pub struct LoopEditor<'a> {
+	state: &'a mut State,
+	playback: Playback,
+	layout: &'a Layout,
+	frame: Frame2,
+	style: Box<dyn StyleSheet>,
+}
+
+impl<'a> LoopEditor<'a> {
+	pub fn new(state: &'a mut State, frame: Frame2, playback: Playback, layout: &'a Layout) -> Self {
+		Self {
+			state,
+			frame,
+			playback,
+			layout,
+			style: Default::default(),
+		}
+	}
+
+	pub fn style(mut self, style: impl Into<Box<dyn StyleSheet>>) -> Self {
+		self.style = style.into();
+		self
+	}
+}
+
+fn get_hover(x: f32, coord: &Coord, region: Option<Range>) -> Hover {
+	let grab = coord.to_board_w(EDGE_GRAB);
+	match region {
+		Some(region) if (x - region.0).abs() < grab => Hover::Start,
+		Some(region) if (x - region.1).abs() < grab => Hover::End,
+		Some(region) if region.contains(x) => Hover::Inside,
+		_ => Hover::Outside,
+	}
+}
+
+impl<'a, B> Widget<RootMessage, Renderer<B>> for LoopEditor<'a>
+where
+	B: Backend + iced_graphics::backend::Text + 'static,
+{
+	fn width(&self) -> Length {
+		Length::Fill
+	}
+
+	fn height(&self) -> Length {
+		Length::Fill
+	}
+
+	fn layout(&self, _renderer: &Renderer<B>, limits: &iced_layout::Limits) -> iced_layout::Node {
+		iced_layout::Node::new(limits.max())
+	}
+
+	fn hash_layout(&self, _action: &mut Hasher) {}
+
+	fn on_event(
+		&mut self,
+		event: Event,
+		layout: iced_native::Layout,
+		cursor_position: iced::Point,
+		messages: &mut Vec<RootMessage>,
+		_renderer: &Renderer<B>,
+		_clipboard: Option<&dyn Clipboard>,
+	) -> event::Status {
+		let lbounds = layout.bounds();
+		let lposition: Point = lbounds.position().into();
+		let mouse_pos = Into::<Point>::into(cursor_position) - lposition.to_vec2();
+		let coord = Coord::new(self.frame, lbounds.size());
+		let at = coord.to_board_x(mouse_pos.x).max(0.0);
+		let region = self.playback.region;
+
+		let captured = match event {
+			Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+				if let Action::Context = self.state.action {
+					self.state.action = Action::Idle;
+					true
+				} else if lbounds.contains(cursor_position) {
+					self.state.action = match get_hover(at, &coord, region) {
+						Hover::Start => Action::MoveStart,
+						Hover::End => Action::MoveEnd,
+						Hover::Inside => Action::Move(at - region.unwrap().0),
+						Hover::Outside => Action::Create(self.layout.quantize_time(at, false)),
+					};
+					true
+				} else {
+					false
+				}
+			}
+			Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+				let time = self.layout.quantize_time(at, false);
+				let new_region = match (&self.state.action, region) {
+					(Action::Create(from), _) => Some(Range(from.min(time), from.max(time))),
+					(Action::MoveStart, Some(region)) => Some(Range(time.min(region.1), region.1)),
+					(Action::MoveEnd, Some(region)) => Some(Range(region.0, time.max(region.0))),
+					(Action::Move(grab), Some(region)) => {
+						let start = self.layout.quantize_time((at - grab).max(0.0), false);
+						Some(Range(start, start + region.size()))
+					}
+					_ => None,
+				};
+				if let Some(new_region) = new_region {
+					if new_region.size() > 0.0 && Some(new_region) != region {
+						messages.push(Message::SetLoop(Some(new_region)).into());
+					}
+				}
+				false
+			}
+			Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+				if self.state.action != Action::Context {
+					self.state.action = Action::Idle;
+				}
+				false
+			}
+			Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+				self.state.action = Action::Idle;
+				if lbounds.contains(cursor_position) && region.is_some() {
+					self.state.context_menu = context_menu::State::new(vec![context_menu::Item::new(
+						"Remove loop region",
+						Message::SetLoop(None).into(),
+					)]);
+					self.state.action = Action::Context;
+					self.state.context_pos = Some(cursor_position);
+					true
+				} else {
+					false
+				}
+			}
+			_ => false,
+		};
+		if captured {
+			event::Status::Captured
+		} else {
+			event::Status::Ignored
+		}
+	}
+
+	fn draw(
+		&self,
+		_renderer: &mut Renderer<B>,
+		_defaults: &Defaults,
+		layout: IcedLayout,
+		cursor_position: iced::Point,
+		_viewport: &Rectangle,
+	) -> (Primitive, mouse::Interaction) {
+		let bounds = layout.bounds();
+		let coord = Coord::new(self.frame, bounds.size());
+		let mouse_pos =
+			coord.to_board_x((Into::<Point>::into(cursor_position) - Into::<Point>::into(bounds.position()).to_vec2()).x);
+
+		let mut primitives = vec![Primitive::Quad {
+			bounds,
+			background: self.style.active().background,
+			border_width: 0.0,
+			border_radius: 0.0,
+			border_color: Color::TRANSPARENT,
+		}];
+		if let Some(region) = self.playback.region {
+			let x0 = bounds.x + coord.to_screen_x(region.0);
+			let x1 = bounds.x + coord.to_screen_x(region.1);
+			let color = if self.playback.looping {
+				Color::from_rgb(0.3, 0.7, 1.0)
+			} else {
+				Color::from_rgb(0.4, 0.4, 0.4)
+			};
+			primitives.push(Primitive::Clip {
+				bounds,
+				offset: Vector::new(0, 0),
+				content: Box::new(Primitive::Quad {
+					bounds: Rectangle {
+						x: x0,
+						y: bounds.y + 2.0,
+						width: (x1 - x0).max(1.0),
+						height: bounds.height - 4.0,
+					},
+					background: Color { a: 0.4, ..color }.into(),
+					border_width: 1.0,
+					border_radius: 0.0,
+					border_color: color,
+				}),
+			});
+		}
+
+		let interaction = match self.state.action {
+			Action::MoveStart | Action::MoveEnd => mouse::Interaction::ResizingHorizontally,
+			Action::Move(_) => mouse::Interaction::Grabbing,
+			_ if !bounds.contains(cursor_position) => mouse::Interaction::Idle,
+			_ => match get_hover(mouse_pos, &coord, self.playback.region) {
+				Hover::Start | Hover::End => mouse::Interaction::ResizingHorizontally,
+				Hover::Inside => mouse::Interaction::Grab,
+				Hover::Outside => mouse::Interaction::Idle,
+			},
+		};
+		(Primitive::Group { primitives }, interaction)
+	}
+
+	fn overlay(&mut self, _layout: iced_layout::Layout) -> Option<overlay::Element<RootMessage, Renderer<B>>> {
+		if let Action::Context = self.state.action {
+			Some(
+				ContextMenu::new(&mut self.state.context_menu)
+					.padding(4)
+					.style(self.style.menu())
+					.overlay(self.state.context_pos.unwrap()),
+			)
+		} else {
+			None
+		}
+	}
+}
+
+impl<'a, B> Into<Element<'a, RootMessage, Renderer<B>>> for LoopEditor<'a>
+where
+	RootMessage: 'a + Clone,
+	B: Backend + iced_graphics::backend::Text + 'static,
+{
+	fn into(self) -> Element<'a, RootMessage, Renderer<B>> {
+		Element::new(self)
+	}
+}
@@ -155,6 +155,16 @@ where
 					match get_hover(at, coord, self.layout) {
 						Some(idx) => {
 							let mut items = vec![context_menu::Item::new("Edit Layout", RootMessage::OpenLayout)];
+							if self.layout.markers[idx].pattern.freq.is_some() {
+								items.push(context_menu::Item::new("Export .scl/.kbm", Message::ExportScala(idx).into()));
+								items.push(context_menu::Item::new("Export .tun", Message::ExportTun(idx).into()));
+							}
+							if self.layout.markers[idx].anchor.is_some() {
+								items.push(context_menu::Item::new(
+									"Free base from note",
+									Message::SetMarkerAnchor(idx, None).into(),
+								));
+							}
 							if idx != 0 {
 								items.push(context_menu::Item::new("Delete marker", Message::DeleteMarker(idx).into()));
 							}
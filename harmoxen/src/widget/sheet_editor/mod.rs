@@ -18,3 +18,12 @@ pub use interval_input::IntervalInput;
 
 pub mod selection;
 pub use selection::Selection;
+
+pub mod arrangement;
+pub use arrangement::Arrangement;
+
+pub mod loop_editor;
+pub use loop_editor::LoopEditor;
+
+pub mod tempo_editor;
+pub use tempo_editor::TempoEditor;
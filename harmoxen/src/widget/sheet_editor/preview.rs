@@ -76,11 +76,7 @@ where
 						let pos = coord.to_board_y(mouse_pos().y);
 						*self.state = State::Active(pos);
 						messages.push(RootMessage::Backend(
-							icp::Event::NotePlay(icp::Note {
-								id: 2000,
-								freq: 2f32.powf(pos),
-							})
-							.into(),
+							icp::Event::NotePlay(icp::Note::new(2000, 2f32.powf(pos))).into(),
 						));
 					}
 				}
@@ -36,15 +36,23 @@ pub struct Selection<'a> {
 	sheet: &'a Sheet,
 	frame: &'a Frame2,
 	selection: &'a HashSet<Index>,
+	hidden_tracks: &'a HashSet<usize>,
 }
 
 impl<'a> Selection<'a> {
-	pub fn new(state: &'a mut State, sheet: &'a Sheet, frame: &'a Frame2, selection: &'a HashSet<Index>) -> Self {
+	pub fn new(
+		state: &'a mut State,
+		sheet: &'a Sheet,
+		frame: &'a Frame2,
+		selection: &'a HashSet<Index>,
+		hidden_tracks: &'a HashSet<usize>,
+	) -> Self {
 		Self {
 			state,
 			sheet,
 			frame,
 			selection,
+			hidden_tracks,
 		}
 	}
 }
@@ -100,7 +108,7 @@ where
 				true
 			}
 			Event::Mouse(mouse::Event::ButtonPressed(_)) => {
-				let note = self.sheet.get_note_at(pos, coord.to_board_h(NOTE_HEIGHT));
+				let note = self.sheet.get_note_at(pos, coord.to_board_h(NOTE_HEIGHT), self.hidden_tracks);
 				false
 			}
 			Event::Mouse(mouse::Event::CursorMoved { x, y }) => match state.action {
@@ -118,7 +126,8 @@ where
 				let sheet = self.sheet;
 				match state.action {
 					Action::SelectionAdd(p0, p1) => {
-						let notes = sheet.get_notes_rect(Rect::from_points(p0, p1), coord.to_board_h(NOTE_HEIGHT));
+						let notes =
+							sheet.get_notes_rect(Rect::from_points(p0, p1), coord.to_board_h(NOTE_HEIGHT), self.hidden_tracks);
 						if !notes.is_empty() {
 							let mut selection = self.selection.clone();
 							selection.extend(notes.into_iter());
@@ -126,7 +135,8 @@ where
 						}
 					}
 					Action::SelectionRemove(p0, p1) => {
-						let notes = sheet.get_notes_rect(Rect::from_points(p0, p1), coord.to_board_h(NOTE_HEIGHT));
+						let notes =
+							sheet.get_notes_rect(Rect::from_points(p0, p1), coord.to_board_h(NOTE_HEIGHT), self.hidden_tracks);
 						if !notes.is_empty() {
 							let selection = self.selection.clone();
 							messages
@@ -0,0 +1,330 @@
+use crate::data::{
+	tempo::{Ramp, TempoChange},
+	Frame2, Layout, Point, Size, TempoMap,
+};
+use crate::state::Message as RootMessage;
+use crate::util::coord::Coord;
+use iced::canvas::{Frame, Path, Stroke};
+use iced_graphics::{Backend, Defaults, Primitive, Renderer};
+use iced_native::{
+	event, layout as iced_layout, mouse, overlay, Clipboard, Color, Element, Event, Font, Hasher, HorizontalAlignment,
+	Layout as IcedLayout, Length, Rectangle, Vector, VerticalAlignment, Widget,
+};
+
+use super::marker_editor::StyleSheet;
+use crate::widget::common::{context_menu, ContextMenu};
+
+// distance in pixels under which a tempo change can be grabbed
+const HANDLE_GRAB: f32 = 4.0;
+// bpm per pixel when dragging a tempo change vertically
+const DRAG_SPEED: f32 = 0.5;
+
+#[derive(PartialEq)]
+pub enum Action {
+	Idle,
+	Drag(usize, f32, f32), // tempo change, mouse y and bpm when the drag started
+	Context,
+}
+
+pub struct State {
+	action: Action,
+	context_menu: context_menu::State<RootMessage>,
+	context_pos: Option<iced::Point>,
+}
+
+impl Default for State {
+	fn default() -> State {
+		State {
+			action: Action::Idle,
+			context_menu: context_menu::State::default(),
+			context_pos: None,
+		}
+	}
+}
+
+// a lane showing the tempo curve, with a handle on each tempo change
+pub struct TempoEditor<'a> {
+	state: &'a mut State,
+	tempo: &'a TempoMap,
+	layout: &'a Layout,
+	frame: Frame2,
+	style: Box<dyn StyleSheet>,
+}
+
+impl<'a> TempoEditor<'a> {
+	pub fn new(state: &'a mut State, frame: Frame2, tempo: &'a TempoMap, layout: &'a Layout) -> Self {
+		Self {
+			state,
+			frame,
+			tempo,
+			layout,
+			style: Default::default(),
+		}
+	}
+
+	pub fn style(mut self, style: impl Into<Box<dyn StyleSheet>>) -> Self {
+		self.style = style.into();
+		self
+	}
+
+	fn get_hover(&self, x: f32, coord: &Coord) -> Option<usize> {
+		let grab = coord.to_board_w(HANDLE_GRAB);
+		self.tempo.changes.iter().rposition(|change| (x - change.at).abs() < grab)
+	}
+
+	// range of bpm shown by the lane
+	fn bpm_range(&self) -> (f32, f32) {
+		let (min, max) = self
+			.tempo
+			.changes
+			.iter()
+			.fold((self.tempo.initial, self.tempo.initial), |(min, max), change| {
+				(min.min(change.bpm), max.max(change.bpm))
+			});
+		let margin = ((max - min) * 0.2).max(10.0);
+		(min - margin, max + margin)
+	}
+}
+
+impl<'a, B> Widget<RootMessage, Renderer<B>> for TempoEditor<'a>
+where
+	B: Backend + iced_graphics::backend::Text + 'static,
+{
+	fn width(&self) -> Length {
+		Length::Fill
+	}
+
+	fn height(&self) -> Length {
+		Length::Fill
+	}
+
+	fn layout(&self, _renderer: &Renderer<B>, limits: &iced_layout::Limits) -> iced_layout::Node {
+		iced_layout::Node::new(limits.max())
+	}
+
+	fn hash_layout(&self, _action: &mut Hasher) {}
+
+	fn on_event(
+		&mut self,
+		event: Event,
+		layout: iced_native::Layout,
+		cursor_position: iced::Point,
+		messages: &mut Vec<RootMessage>,
+		_renderer: &Renderer<B>,
+		_clipboard: Option<&dyn Clipboard>,
+	) -> event::Status {
+		let lbounds = layout.bounds();
+		let lposition: Point = lbounds.position().into();
+		let mouse_pos = Into::<Point>::into(cursor_position) - lposition.to_vec2();
+		let coord = Coord::new(self.frame, lbounds.size());
+		let at = coord.to_board_x(mouse_pos.x).max(0.0);
+
+		let captured = match event {
+			Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+				if let Action::Context = self.state.action {
+					self.state.action = Action::Idle;
+					true
+				} else if lbounds.contains(cursor_position) {
+					if let Some(idx) = self.get_hover(at, &coord) {
+						self.state.action = Action::Drag(idx, mouse_pos.y, self.tempo.changes[idx].bpm);
+						true
+					} else {
+						false
+					}
+				} else {
+					false
+				}
+			}
+			Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+				if let Action::Drag(idx, y, bpm) = self.state.action {
+					let change = TempoChange {
+						at: self.layout.quantize_time(at, false),
+						bpm: ((bpm + (y - mouse_pos.y) * DRAG_SPEED) * 10.0).round().max(100.0) / 10.0,
+						ramp: self.tempo.changes[idx].ramp,
+					};
+					if change != self.tempo.changes[idx] {
+						messages.push(RootMessage::TempoChangeSet(idx, change));
+						// the change is moved past its neighbours the same way by the map
+						let new_idx = self
+							.tempo
+							.changes
+							.iter()
+							.enumerate()
+							.filter(|&(i, c)| i != idx && c.at <= change.at)
+							.count();
+						self.state.action = Action::Drag(new_idx, y, bpm);
+					}
+				}
+				false
+			}
+			Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+				if let Action::Drag(..) = self.state.action {
+					self.state.action = Action::Idle;
+				}
+				false
+			}
+			Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+				self.state.action = Action::Idle;
+				if lbounds.contains(cursor_position) {
+					let items = match self.get_hover(at, &coord) {
+						Some(idx) => {
+							let change = self.tempo.changes[idx];
+							let (text, ramp) = match change.ramp {
+								Ramp::Step => ("Ramp from previous tempo", Ramp::Linear),
+								Ramp::Linear => ("Jump to this tempo", Ramp::Step),
+							};
+							vec![
+								context_menu::Item::new(text, RootMessage::TempoChangeSet(idx, TempoChange { ramp, ..change })),
+								context_menu::Item::new("Delete tempo change", RootMessage::TempoChangeDelete(idx)),
+							]
+						}
+						None => {
+							let at = self.layout.quantize_time(at, false);
+							let change = TempoChange {
+								at,
+								bpm: self.tempo.tempo_at(at),
+								ramp: Ramp::Step,
+							};
+							vec![context_menu::Item::new(
+								"Add tempo change",
+								RootMessage::TempoChangeAdd(change),
+							)]
+						}
+					};
+					self.state.context_menu = context_menu::State::new(items);
+					self.state.action = Action::Context;
+					self.state.context_pos = Some(cursor_position);
+					true
+				} else {
+					false
+				}
+			}
+			_ => false,
+		};
+		if captured {
+			event::Status::Captured
+		} else {
+			event::Status::Ignored
+		}
+	}
+
+	fn draw(
+		&self,
+		_renderer: &mut Renderer<B>,
+		_defaults: &Defaults,
+		layout: IcedLayout,
+		cursor_position: iced::Point,
+		_viewport: &Rectangle,
+	) -> (Primitive, mouse::Interaction) {
+		let bounds = layout.bounds();
+		let size = Size::new(bounds.width, bounds.height);
+		let coord = Coord::new(self.frame, size);
+		let mouse_pos =
+			coord.to_board_x((Into::<Point>::into(cursor_position) - Into::<Point>::into(bounds.position()).to_vec2()).x);
+		let (min, max) = self.bpm_range();
+		let to_screen_y = |bpm: f32| size.height * (1.0 - (bpm - min) / (max - min));
+		let color = Color::from_rgb(0.9, 0.6, 0.2);
+
+		// tempo curve, sampled every few pixels so that ramps look smooth
+		let mut frame = Frame::new(size);
+		let nsteps = (size.width / 4.0).ceil().max(1.0) as usize;
+		let curve = Path::new(|b| {
+			for i in 0..=nsteps {
+				let x = size.width * i as f32 / nsteps as f32;
+				let y = to_screen_y(self.tempo.tempo_at(coord.to_board_x(x)));
+				if i == 0 {
+					b.move_to([x, y].into());
+				} else {
+					b.line_to([x, y].into());
+				}
+			}
+		});
+		frame.stroke(
+			&curve,
+			Stroke {
+				width: 1.0,
+				color,
+				..Default::default()
+			},
+		);
+
+		let mut labels = vec![];
+		for change in &self.tempo.changes {
+			let x = coord.to_screen_x(change.at);
+			let y = to_screen_y(change.bpm);
+			frame.fill(&Path::rectangle([x - 2.0, y - 2.0].into(), [4.0, 4.0].into()), color);
+			labels.push(Primitive::Text {
+				content: format!("{}", change.bpm),
+				size: (size.height * 0.5).max(8.0),
+				font: Font::Default,
+				color,
+				bounds: Rectangle {
+					x: bounds.x + x + 4.0,
+					y: bounds.y + bounds.height / 2.0,
+					width: 64.0,
+					height: bounds.height,
+				},
+				horizontal_alignment: HorizontalAlignment::Left,
+				vertical_alignment: VerticalAlignment::Center,
+			});
+		}
+
+		let interaction = if let Action::Drag(..) = self.state.action {
+			mouse::Interaction::Grabbing
+		} else if bounds.contains(cursor_position) && self.get_hover(mouse_pos, &coord).is_some() {
+			mouse::Interaction::Grab
+		} else {
+			mouse::Interaction::Idle
+		};
+		(
+			Primitive::Group {
+				primitives: vec![
+					Primitive::Quad {
+						bounds,
+						background: self.style.active().background,
+						border_width: 0.0,
+						border_radius: 0.0,
+						border_color: Color::TRANSPARENT,
+					},
+					Primitive::Clip {
+						bounds,
+						offset: Vector::new(0, 0),
+						content: Box::new(Primitive::Group {
+							primitives: vec![
+								Primitive::Translate {
+									translation: Vector::new(bounds.x, bounds.y),
+									content: Box::new(frame.into_geometry().into_primitive()),
+								},
+								Primitive::Group { primitives: labels },
+							],
+						}),
+					},
+				],
+			},
+			interaction,
+		)
+	}
+
+	fn overlay(&mut self, _layout: iced_layout::Layout) -> Option<overlay::Element<RootMessage, Renderer<B>>> {
+		if let Action::Context = self.state.action {
+			Some(
+				ContextMenu::new(&mut self.state.context_menu)
+					.padding(4)
+					.style(self.style.menu())
+					.overlay(self.state.context_pos.unwrap()),
+			)
+		} else {
+			None
+		}
+	}
+}
+
+impl<'a, B> Into<Element<'a, RootMessage, Renderer<B>>> for TempoEditor<'a>
+where
+	RootMessage: 'a + Clone,
+	B: Backend + iced_graphics::backend::Text + 'static,
+{
+	fn into(self) -> Element<'a, RootMessage, Renderer<B>> {
+		Element::new(self)
+	}
+}